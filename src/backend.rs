@@ -0,0 +1,284 @@
+//Where the screen is drawn to, the terminal or (for tests) a grid in memory
+use crate::{
+    screen::{Cell, Color, Style},
+    text,
+};
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+pub trait Backend: fmt::Debug {
+    //Columns and rows
+    fn size(&self) -> io::Result<(u16, u16)>;
+    fn clear(&mut self) -> io::Result<()>;
+    //Draws each cell at its position (0,0 based), cells covered by a wide grapheme are skipped
+    fn draw(&mut self, cells: &[(u16, u16, &Cell)]) -> io::Result<()>;
+    fn set_cursor(&mut self, x: u16, y: u16, shape: CursorShape) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+//Collects the escape codes and writes them to stdout all at once on flush.
+//It remembers where the terminal's cursor is and which style is set,
+//so a run of cells is written without moving or restyling between them.
+#[derive(Debug, Default)]
+pub struct TermionBackend {
+    buffer: Vec<u8>,
+    position: Option<(u16, u16)>,
+    style: Option<Style>,
+}
+
+impl TermionBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn write_style(&mut self, style: Style) -> io::Result<()> {
+        use termion::{color, style};
+
+        write!(self.buffer, "{}", style::Reset)?;
+        if style.bold {
+            write!(self.buffer, "{}", style::Bold)?;
+        }
+        if style.faint {
+            write!(self.buffer, "{}", style::Faint)?;
+        }
+        if style.invert {
+            write!(self.buffer, "{}", style::Invert)?;
+        }
+        match style.fg {
+            Color::Reset => {}
+            Color::Blue => write!(self.buffer, "{}", color::Fg(color::Blue))?,
+            Color::Green => write!(self.buffer, "{}", color::Fg(color::Green))?,
+            Color::Cyan => write!(self.buffer, "{}", color::Fg(color::Cyan))?,
+            Color::Yellow => write!(self.buffer, "{}", color::Fg(color::Yellow))?,
+            Color::Black => write!(self.buffer, "{}", color::Fg(color::Black))?,
+        }
+        match style.bg {
+            Color::Reset => {}
+            Color::Blue => write!(self.buffer, "{}", color::Bg(color::Blue))?,
+            Color::Green => write!(self.buffer, "{}", color::Bg(color::Green))?,
+            Color::Cyan => write!(self.buffer, "{}", color::Bg(color::Cyan))?,
+            Color::Yellow => write!(self.buffer, "{}", color::Bg(color::Yellow))?,
+            Color::Black => write!(self.buffer, "{}", color::Bg(color::Black))?,
+        }
+        Ok(())
+    }
+}
+
+impl Backend for TermionBackend {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        termion::terminal_size()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.position = None;
+        write!(
+            self.buffer,
+            "{}{}",
+            termion::cursor::Goto(1, 1),
+            termion::clear::All
+        )
+    }
+
+    fn draw(&mut self, cells: &[(u16, u16, &Cell)]) -> io::Result<()> {
+        if cells.is_empty() {
+            return Ok(());
+        }
+        //Shown again by set_cursor, so it doesn't jump around while drawing
+        write!(self.buffer, "{}", termion::cursor::Hide)?;
+        for (x, y, cell) in cells {
+            if cell.is_continuation() {
+                continue;
+            }
+            if self.position != Some((*x, *y)) {
+                //Goto is 1,1 based
+                write!(self.buffer, "{}", termion::cursor::Goto(x + 1, y + 1))?;
+            }
+            if self.style != Some(cell.style) {
+                self.write_style(cell.style)?;
+                self.style = Some(cell.style);
+            }
+            self.buffer.extend(cell.symbol.as_bytes());
+            self.position = Some((x + text::grapheme_width(&cell.symbol) as u16, *y));
+        }
+        self.style = None;
+        write!(self.buffer, "{}", termion::style::Reset)
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16, shape: CursorShape) -> io::Result<()> {
+        self.position = Some((x, y));
+        write!(
+            self.buffer,
+            "{}{}",
+            termion::cursor::Show,
+            termion::cursor::Goto(x + 1, y + 1)
+        )?;
+        match shape {
+            CursorShape::Block => write!(self.buffer, "{}", termion::cursor::SteadyBlock),
+            CursorShape::Bar => write!(self.buffer, "{}", termion::cursor::SteadyBar),
+            CursorShape::Underline => write!(self.buffer, "{}", termion::cursor::SteadyUnderline),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&self.buffer)?;
+        self.buffer.clear();
+        stdout.flush()
+    }
+}
+
+#[cfg(test)]
+pub use memory::MemoryBackend;
+
+#[cfg(test)]
+mod memory {
+    use super::{Backend, CursorShape};
+    use crate::screen::{Cell, Grid};
+    use std::{cell::RefCell, io, rc::Rc};
+
+    #[derive(Debug)]
+    struct Screen {
+        grid: Grid,
+        cursor: (u16, u16),
+        shape: CursorShape,
+        pending: usize, //Cells drawn since the last flush
+        drawn: usize,
+    }
+
+    //Keeps what would be on the terminal. Clones share the screen,
+    //so a test can look at it after handing a clone to the editor.
+    #[derive(Debug, Clone)]
+    pub struct MemoryBackend {
+        screen: Rc<RefCell<Screen>>,
+    }
+
+    impl MemoryBackend {
+        pub fn new(width: u16, height: u16) -> Self {
+            Self {
+                screen: Rc::new(RefCell::new(Screen {
+                    grid: Grid::new(width as usize, height as usize),
+                    cursor: (0, 0),
+                    shape: CursorShape::Block,
+                    pending: 0,
+                    drawn: 0,
+                })),
+            }
+        }
+
+        //Like the user resizing the terminal, what was on it is gone
+        pub fn resize(&self, width: u16, height: u16) {
+            self.screen.borrow_mut().grid = Grid::new(width as usize, height as usize);
+        }
+
+        pub fn rows(&self) -> Vec<String> {
+            let screen = self.screen.borrow();
+            (0..screen.grid.height())
+                .map(|y| screen.grid.row_text(y))
+                .collect()
+        }
+
+        pub fn cell(&self, x: usize, y: usize) -> Cell {
+            self.screen.borrow().grid.cell(x, y).clone()
+        }
+
+        //How many cells the last flushed frame drew
+        pub fn drawn(&self) -> usize {
+            self.screen.borrow().drawn
+        }
+
+        pub fn cursor(&self) -> (u16, u16, CursorShape) {
+            let screen = self.screen.borrow();
+            (screen.cursor.0, screen.cursor.1, screen.shape)
+        }
+    }
+
+    impl Backend for MemoryBackend {
+        fn size(&self) -> io::Result<(u16, u16)> {
+            let screen = self.screen.borrow();
+            Ok((screen.grid.width() as u16, screen.grid.height() as u16))
+        }
+
+        fn clear(&mut self) -> io::Result<()> {
+            let mut screen = self.screen.borrow_mut();
+            screen.grid = Grid::new(screen.grid.width(), screen.grid.height());
+            Ok(())
+        }
+
+        fn draw(&mut self, cells: &[(u16, u16, &Cell)]) -> io::Result<()> {
+            let mut screen = self.screen.borrow_mut();
+            screen.pending += cells.len();
+            for (x, y, cell) in cells {
+                screen
+                    .grid
+                    .set_cell(*x as usize, *y as usize, (*cell).clone());
+            }
+            Ok(())
+        }
+
+        fn set_cursor(&mut self, x: u16, y: u16, shape: CursorShape) -> io::Result<()> {
+            let mut screen = self.screen.borrow_mut();
+            screen.cursor = (x, y);
+            screen.shape = shape;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let mut screen = self.screen.borrow_mut();
+            screen.drawn = std::mem::take(&mut screen.pending);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Backend, TermionBackend};
+    use crate::screen::{Cell, Style};
+
+    #[test]
+    fn runs() {
+        let mut backend = TermionBackend::new();
+        let cell = |symbol: &str, style| Cell {
+            symbol: symbol.to_string(),
+            style,
+        };
+        let (a, wide, b, c) = (
+            cell("a", Style::default()),
+            cell("日", Style::default()),
+            cell("b", Style::bold()),
+            cell("c", Style::bold()),
+        );
+        let (cont, far) = (cell("", Style::default()), cell("d", Style::bold()));
+        backend
+            .draw(&[
+                (0, 0, &a),
+                (1, 0, &wide),
+                (2, 0, &cont),
+                (3, 0, &b),
+                (4, 0, &c),
+                (0, 2, &far),
+            ])
+            .unwrap();
+        let reset = termion::style::Reset.to_string();
+        let bold = termion::style::Bold.to_string();
+        let goto = |x, y| termion::cursor::Goto(x, y).to_string();
+        assert_eq!(
+            String::from_utf8(backend.buffer).unwrap(),
+            format!(
+                "{}{}{reset}a日{reset}{bold}bc{}d{reset}",
+                termion::cursor::Hide,
+                goto(1, 1),
+                goto(1, 3)
+            )
+        );
+    }
+}
@@ -1,57 +1,512 @@
-use crate::min_todo::Buffer;
+//Turns the bytes read from the terminal into key presses.
+//Input arrives in chunks that don't line up with the keys, a read can hold several keys
+//(fast typing, pastes) or only a part of one (a long escape sequence, a multibyte character),
+//so bytes are buffered until they make up a whole key.
+use std::collections::VecDeque;
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Character {
     Display(char),
     Esc,
+    Enter,
+    Tab,
+    BackTab, //Shift-Tab
     Backspace,
     Delete,
+    Insert,
     ArrowUp,
     ArrowDown,
     ArrowLeft,
     ArrowRight,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F(u8),
+    Ctrl(char), //A control character, Ctrl with a letter is stored lowercase
 }
 
-pub fn parse_char(buffer: Buffer) -> Option<Character> {
-    let zeros = buffer.iter().filter(|x| **x == 0).count();
-
-    let values = buffer.len() - zeros;
-
-    match values {
-        1 => {
-            match *buffer.first().unwrap() as char {
-                //Esc
-                '\u{1B}' => Some(Character::Esc),
-                //Backspace
-                '\u{7F}' => Some(Character::Backspace),
-                ch => {
-                    if ch.is_ascii_graphic() || ch == ' ' {
-                        Some(Character::Display(ch))
-                    } else {
-                        None
-                    }
+//Modifiers the terminal reported together with a key. Control characters
+//are already Character::Ctrl, so this is mostly for special keys and Alt.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Self = Self {
+        shift: false,
+        alt: false,
+        ctrl: false,
+    };
+    const ALT: Self = Self {
+        shift: false,
+        alt: true,
+        ctrl: false,
+    };
+
+    //The xterm encoding, the parameter is 1 + a bitmask of the modifiers
+    fn from_param(param: u32) -> Self {
+        let mask = param.saturating_sub(1);
+        Self {
+            shift: mask & 1 != 0,
+            alt: mask & 2 != 0,
+            ctrl: mask & 4 != 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Event {
+    Key(Character, Modifiers),
+    //Text pasted while bracketed paste is on, so it's not mistaken for commands
+    Paste(String),
+    //The terminal changed its size, this doesn't come from the input but from SIGWINCH
+    Resize,
+}
+
+impl Event {
+    fn key(c: Character) -> Self {
+        Self::Key(c, Modifiers::NONE)
+    }
+}
+
+const ESC: u8 = 0x1b;
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+//What the first bytes of the buffer turned out to be
+enum Decoded {
+    Event(Event, usize), //The event and how many bytes it took
+    Skip(usize),         //A sequence we don't know, dropped
+    Incomplete,
+}
+
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buffer: VecDeque<u8>,
+    paste: Option<Vec<u8>>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend(bytes);
+    }
+
+    //Whether there are bytes waiting for the rest of their key
+    pub fn is_pending(&self) -> bool {
+        !self.buffer.is_empty() || self.paste.is_some()
+    }
+
+    //Inside a bracketed paste, waiting for its end marker
+    pub fn is_pasting(&self) -> bool {
+        self.paste.is_some()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.decode(false)
+    }
+
+    //Called once no more input came in time, so whatever is buffered is taken as it is:
+    //a lone ESC is the Esc key and not the beginning of a sequence. A paste stays open, a slow
+    //connection can leave gaps in it and the rest of the text must not be taken as keys.
+    pub fn flush(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(event) = self.decode(true) {
+            events.push(event);
+        }
+        events
+    }
+
+    fn decode(&mut self, flush: bool) -> Option<Event> {
+        loop {
+            if self.paste.is_some() {
+                return self.decode_paste();
+            }
+            let bytes = self.buffer.make_contiguous();
+            if bytes.is_empty() {
+                return None;
+            }
+            match decode(bytes, flush) {
+                Decoded::Event(Event::Paste(_), len) => {
+                    //Only the start marker, the text follows
+                    self.buffer.drain(..len);
+                    self.paste = Some(Vec::new());
+                }
+                Decoded::Event(event, len) => {
+                    self.buffer.drain(..len);
+                    return Some(event);
                 }
+                Decoded::Skip(len) => {
+                    self.buffer.drain(..len);
+                }
+                Decoded::Incomplete => return None,
             }
         }
-        3 => {
-            match buffer[..3] {
-                //Arrow Up
-                [27, 91, 65] => Some(Character::ArrowUp),
-                //Arrow Down
-                [27, 91, 66] => Some(Character::ArrowDown),
-                //Arrow Left
-                [27, 91, 68] => Some(Character::ArrowLeft),
-                //Arrow Right
-                [27, 91, 67] => Some(Character::ArrowRight),
-                _ => None,
+    }
+
+    //The end marker got lost, the paste is taken as it is so we don't wait for it forever
+    pub fn end_paste(&mut self) -> Option<Event> {
+        let mut paste = self.paste.take()?;
+        paste.extend(self.buffer.drain(..));
+        Some(Event::Paste(String::from_utf8_lossy(&paste).into_owned()))
+    }
+
+    fn decode_paste(&mut self) -> Option<Event> {
+        let paste = self.paste.as_mut()?;
+        paste.extend(self.buffer.drain(..));
+        let end = paste
+            .windows(PASTE_END.len())
+            .position(|window| window == PASTE_END)?;
+        let rest = paste.split_off(end);
+        self.buffer.extend(&rest[PASTE_END.len()..]);
+        let text = self.paste.take()?;
+        Some(Event::Paste(String::from_utf8_lossy(&text).into_owned()))
+    }
+}
+
+fn decode(bytes: &[u8], flush: bool) -> Decoded {
+    match bytes[0] {
+        ESC => decode_escape(bytes, flush),
+        _ => decode_plain(bytes, flush),
+    }
+}
+
+//Anything that doesn't start with ESC
+fn decode_plain(bytes: &[u8], flush: bool) -> Decoded {
+    let c = match bytes[0] {
+        b'\r' | b'\n' => Character::Enter,
+        b'\t' => Character::Tab,
+        0x7f | 0x08 => Character::Backspace,
+        0x00 => Character::Ctrl(' '),
+        b @ 0x01..=0x1a => Character::Ctrl((b - 1 + b'a') as char),
+        b @ 0x1c..=0x1f => Character::Ctrl((b - 0x1c + b'\\') as char),
+        b if b.is_ascii() => Character::Display(b as char),
+        b => return decode_utf8(bytes, b, flush),
+    };
+    Decoded::Event(Event::key(c), 1)
+}
+
+fn decode_utf8(bytes: &[u8], first: u8, flush: bool) -> Decoded {
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => {
+            return Decoded::Event(
+                Event::key(Character::Display(char::REPLACEMENT_CHARACTER)),
+                1,
+            )
+        }
+    };
+    if bytes.len() < len && !flush {
+        return Decoded::Incomplete;
+    }
+    match bytes.get(..len).and_then(|b| std::str::from_utf8(b).ok()) {
+        Some(s) => Decoded::Event(
+            Event::key(Character::Display(s.chars().next().unwrap())),
+            len,
+        ),
+        //Broken UTF-8, skip just the first byte so nothing after it is lost
+        None => Decoded::Event(
+            Event::key(Character::Display(char::REPLACEMENT_CHARACTER)),
+            1,
+        ),
+    }
+}
+
+fn decode_escape(bytes: &[u8], flush: bool) -> Decoded {
+    match bytes.get(1) {
+        None if flush => Decoded::Event(Event::key(Character::Esc), 1),
+        None => Decoded::Incomplete,
+        Some(b'[') => match decode_csi(&bytes[2..]) {
+            Some(Decoded::Event(event, len)) => Decoded::Event(event, len + 2),
+            Some(Decoded::Skip(len)) => Decoded::Skip(len + 2),
+            Some(Decoded::Incomplete) if !flush => Decoded::Incomplete,
+            //Alt-[ and whatever comes after it
+            Some(Decoded::Incomplete) | None => alt(Character::Display('[')),
+        },
+        Some(b'O') => match bytes.get(2).and_then(|b| ss3_key(*b)) {
+            Some(c) => Decoded::Event(Event::key(c), 3),
+            None if bytes.len() < 3 && !flush => Decoded::Incomplete,
+            None => alt(Character::Display('O')),
+        },
+        //Esc pressed twice, the first one can't be an Alt
+        Some(&ESC) => Decoded::Event(Event::key(Character::Esc), 1),
+        //Alt is sent as ESC before the key
+        Some(_) => match decode_plain(&bytes[1..], flush) {
+            Decoded::Event(Event::Key(c, _), len) => {
+                Decoded::Event(Event::Key(c, Modifiers::ALT), len + 1)
             }
+            Decoded::Incomplete => Decoded::Incomplete,
+            _ => Decoded::Event(Event::key(Character::Esc), 1),
+        },
+    }
+}
+
+fn alt(c: Character) -> Decoded {
+    Decoded::Event(Event::Key(c, Modifiers::ALT), 2)
+}
+
+//The keys sent as ESC O and one letter
+fn ss3_key(b: u8) -> Option<Character> {
+    Some(match b {
+        b'A' => Character::ArrowUp,
+        b'B' => Character::ArrowDown,
+        b'C' => Character::ArrowRight,
+        b'D' => Character::ArrowLeft,
+        b'H' => Character::Home,
+        b'F' => Character::End,
+        b'P'..=b'S' => Character::F(b - b'P' + 1),
+        _ => return None,
+    })
+}
+
+//Parses what comes after ESC [, it's parameters, intermediate bytes and a final byte.
+//Returns None if it isn't a control sequence at all.
+fn decode_csi(bytes: &[u8]) -> Option<Decoded> {
+    let end = bytes.iter().position(|b| !(0x20..=0x3f).contains(b));
+    let Some(end) = end else {
+        return Some(Decoded::Incomplete);
+    };
+    let last = bytes[end];
+    if !(0x40..=0x7e).contains(&last) {
+        return None;
+    }
+    let len = end + 1;
+    let params: Vec<u32> = std::str::from_utf8(&bytes[..end])
+        .unwrap_or_default()
+        .split(';')
+        .map(|p| p.parse().unwrap_or(0))
+        .collect();
+    let modifiers = Modifiers::from_param(params.get(1).copied().unwrap_or(1));
+
+    let c = match (last, params[0]) {
+        (b'~', 200) => return Some(Decoded::Event(Event::Paste(String::new()), len)),
+        (b'~', n) => match n {
+            1 | 7 => Character::Home,
+            2 => Character::Insert,
+            3 => Character::Delete,
+            4 | 8 => Character::End,
+            5 => Character::PageUp,
+            6 => Character::PageDown,
+            11..=15 => Character::F((n - 10) as u8),
+            17..=21 => Character::F((n - 11) as u8),
+            23 | 24 => Character::F((n - 12) as u8),
+            _ => return Some(Decoded::Skip(len)),
+        },
+        (b'Z', _) => Character::BackTab,
+        (b, _) => match ss3_key(b) {
+            Some(c) => c,
+            None => return Some(Decoded::Skip(len)),
+        },
+    };
+    Some(Decoded::Event(Event::Key(c, modifiers), len))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Character, Decoder, Event, Modifiers};
+
+    fn key(c: Character) -> Event {
+        Event::Key(c, Modifiers::NONE)
+    }
+
+    fn with(c: Character, shift: bool, alt: bool, ctrl: bool) -> Event {
+        Event::Key(c, Modifiers { shift, alt, ctrl })
+    }
+
+    //Everything that was fed in, as if the input stopped afterwards
+    fn decode_all(bytes: &[u8]) -> Vec<Event> {
+        let mut decoder = Decoder::new();
+        decoder.feed(bytes);
+        let mut events = Vec::new();
+        while let Some(event) = decoder.next_event() {
+            events.push(event);
+        }
+        events.extend(decoder.flush());
+        events.extend(decoder.end_paste());
+        assert!(!decoder.is_pending());
+        events
+    }
+
+    #[test]
+    fn decode_table() {
+        use Character::*;
+        let table: &[(&[u8], Vec<Event>)] = &[
+            (b"a", vec![key(Display('a'))]),
+            (b" ~", vec![key(Display(' ')), key(Display('~'))]),
+            (b"\r", vec![key(Enter)]),
+            (b"\n", vec![key(Enter)]),
+            (b"\t", vec![key(Tab)]),
+            (b"\x7f", vec![key(Backspace)]),
+            (b"\x08", vec![key(Backspace)]),
+            (b"\x00", vec![key(Ctrl(' '))]),
+            (b"\x01", vec![key(Ctrl('a'))]),
+            (b"\x03", vec![key(Ctrl('c'))]),
+            (b"\x12", vec![key(Ctrl('r'))]),
+            (b"\x1a", vec![key(Ctrl('z'))]),
+            (
+                b"\x1c\x1d\x1e\x1f",
+                vec![
+                    key(Ctrl('\\')),
+                    key(Ctrl(']')),
+                    key(Ctrl('^')),
+                    key(Ctrl('_')),
+                ],
+            ),
+            //UTF-8
+            ("é".as_bytes(), vec![key(Display('é'))]),
+            (
+                "日本".as_bytes(),
+                vec![key(Display('日')), key(Display('本'))],
+            ),
+            ("🦀".as_bytes(), vec![key(Display('🦀'))]),
+            (b"\xff", vec![key(Display('\u{fffd}'))]),
+            (b"\xc3a", vec![key(Display('\u{fffd}')), key(Display('a'))]),
+            (
+                b"\xe6\x97",
+                vec![key(Display('\u{fffd}')), key(Display('\u{fffd}'))],
+            ),
+            //Esc and Alt
+            (b"\x1b", vec![key(Esc)]),
+            (b"\x1b\x1b", vec![key(Esc), key(Esc)]),
+            (b"\x1bx", vec![with(Display('x'), false, true, false)]),
+            (b"\x1b\x12", vec![with(Ctrl('r'), false, true, false)]),
+            (b"\x1b[", vec![with(Display('['), false, true, false)]),
+            (b"\x1bO", vec![with(Display('O'), false, true, false)]),
+            //Arrows, CSI and SS3
+            (b"\x1b[A", vec![key(ArrowUp)]),
+            (b"\x1b[B", vec![key(ArrowDown)]),
+            (b"\x1b[C", vec![key(ArrowRight)]),
+            (b"\x1b[D", vec![key(ArrowLeft)]),
+            (b"\x1bOA", vec![key(ArrowUp)]),
+            (b"\x1bOD", vec![key(ArrowLeft)]),
+            (b"\x1b[1;5C", vec![with(ArrowRight, false, false, true)]),
+            (b"\x1b[1;5D", vec![with(ArrowLeft, false, false, true)]),
+            (b"\x1b[1;2A", vec![with(ArrowUp, true, false, false)]),
+            (b"\x1b[1;3B", vec![with(ArrowDown, false, true, false)]),
+            (b"\x1b[1;8D", vec![with(ArrowLeft, true, true, true)]),
+            //Home, End, Insert, Delete, PageUp, PageDown
+            (b"\x1b[H", vec![key(Home)]),
+            (b"\x1b[F", vec![key(End)]),
+            (b"\x1bOH", vec![key(Home)]),
+            (b"\x1bOF", vec![key(End)]),
+            (b"\x1b[1~", vec![key(Home)]),
+            (b"\x1b[7~", vec![key(Home)]),
+            (b"\x1b[4~", vec![key(End)]),
+            (b"\x1b[8~", vec![key(End)]),
+            (b"\x1b[2~", vec![key(Insert)]),
+            (b"\x1b[3~", vec![key(Delete)]),
+            (b"\x1b[3;5~", vec![with(Delete, false, false, true)]),
+            (b"\x1b[5~", vec![key(PageUp)]),
+            (b"\x1b[6~", vec![key(PageDown)]),
+            (b"\x1b[Z", vec![key(BackTab)]),
+            //F-keys
+            (b"\x1bOP", vec![key(F(1))]),
+            (b"\x1bOS", vec![key(F(4))]),
+            (b"\x1b[1;2P", vec![with(F(1), true, false, false)]),
+            (b"\x1b[11~", vec![key(F(1))]),
+            (b"\x1b[15~", vec![key(F(5))]),
+            (b"\x1b[17~", vec![key(F(6))]),
+            (b"\x1b[21~", vec![key(F(10))]),
+            (b"\x1b[23~", vec![key(F(11))]),
+            (b"\x1b[24;5~", vec![with(F(12), false, false, true)]),
+            //Unknown sequences are dropped whole
+            (b"\x1b[99~a", vec![key(Display('a'))]),
+            (b"\x1b[?1;2ca", vec![key(Display('a'))]),
+            //Several keys in one read
+            (
+                b"ab\x1b[Ac\x1bd",
+                vec![
+                    key(Display('a')),
+                    key(Display('b')),
+                    key(ArrowUp),
+                    key(Display('c')),
+                    with(Display('d'), false, true, false),
+                ],
+            ),
+            (b"\x1b[3~\x1b", vec![key(Delete), key(Esc)]),
+            //Bracketed paste
+            (
+                b"\x1b[200~hi\x1b[A\r\x1b[201~x",
+                vec![Event::Paste(String::from("hi\x1b[A\r")), key(Display('x'))],
+            ),
+            (b"\x1b[200~\x1b[201~", vec![Event::Paste(String::new())]),
+            (b"\x1b[200~lost", vec![Event::Paste(String::from("lost"))]),
+        ];
+        for (bytes, events) in table {
+            assert_eq!(&decode_all(bytes), events, "{bytes:?}");
         }
-        4 => {
-            match buffer {
-                //Delete
-                [27, 91, 51, 126] => Some(Character::Delete),
-                _ => None,
+    }
+
+    //Keys cut into pieces by the reads have to come out the same
+    #[test]
+    fn split_reads() {
+        let input = "a\x1b[1;5Cé\x1b[200~p\x1b[201~\x1b[24~b".as_bytes();
+        let expected = decode_all(input);
+        for split in 1..input.len() {
+            let mut decoder = Decoder::new();
+            let mut events = Vec::new();
+            for chunk in input.chunks(split) {
+                decoder.feed(chunk);
+                while let Some(event) = decoder.next_event() {
+                    events.push(event);
+                }
             }
+            assert!(!decoder.is_pending());
+            assert_eq!(events, expected, "{split}");
         }
-        _ => None,
+    }
+
+    #[test]
+    fn escape_timeout() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"\x1b");
+        //It could still be the start of a sequence
+        assert_eq!(decoder.next_event(), None);
+        assert!(decoder.is_pending());
+        decoder.feed(b"[");
+        assert_eq!(decoder.next_event(), None);
+        decoder.feed(b"A");
+        assert_eq!(decoder.next_event(), Some(key(Character::ArrowUp)));
+
+        //Nothing followed in time, so it was just Esc
+        decoder.feed(b"\x1b");
+        assert_eq!(decoder.flush(), vec![key(Character::Esc)]);
+        assert!(!decoder.is_pending());
+        decoder.feed(b"j");
+        assert_eq!(decoder.next_event(), Some(key(Character::Display('j'))));
+    }
+
+    //A slow paste isn't ended by the gaps in it, only by its end marker
+    #[test]
+    fn paste_timeout() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"\x1b[200~dd\x1b");
+        assert_eq!(decoder.next_event(), None);
+        assert_eq!(decoder.flush(), vec![]);
+        assert!(decoder.is_pasting());
+        decoder.feed(b"[201");
+        assert_eq!(decoder.flush(), vec![]);
+        decoder.feed(b"~\x1b");
+        assert_eq!(decoder.next_event(), Some(Event::Paste(String::from("dd"))));
+        assert!(!decoder.is_pasting());
+        assert_eq!(decoder.flush(), vec![key(Character::Esc)]);
+
+        //The end marker never came
+        decoder.feed(b"\x1b[200~lost\x1b[20");
+        assert_eq!(decoder.flush(), vec![]);
+        assert_eq!(
+            decoder.end_paste(),
+            Some(Event::Paste(String::from("lost\x1b[20")))
+        );
+        assert!(!decoder.is_pending());
+        assert_eq!(decoder.end_paste(), None);
     }
 }
@@ -0,0 +1,493 @@
+use crate::{date::Date, query::Query, sort::SortKey, todo_item::Tag};
+use std::{error::Error, fmt, path::PathBuf};
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExCommand {
+    Write { path: Option<PathBuf>, force: bool },
+    Quit { force: bool },
+    WriteQuit { force: bool },
+    Edit { path: Option<PathBuf>, force: bool },
+    SaveAs { path: PathBuf, force: bool },
+    Tag(String),
+    Untag(String),
+    Due(Option<Date>),
+    Archive,
+    Set(Setting),
+    NoHighlight,           //Hides the matches of the last search until the next one
+    Filter(Option<Query>), //Shows only the matching lines, all of them again without a query
+    Sort { keys: Vec<SortKey>, reverse: bool },
+}
+
+//Options changed with :set
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Setting {
+    Wrap(bool), //Soft wrap long lines instead of scrolling sideways
+}
+
+impl Setting {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "wrap" => Some(Self::Wrap(true)),
+            "nowrap" => Some(Self::Wrap(false)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExCommandError {
+    NotAnEditorCommand(String),
+    ArgumentRequired(String),
+    TrailingCharacters(String),
+    InvalidArgument(String),
+    NoWriteSinceLastChange,
+    NoFileName,
+    FileExists,
+}
+
+impl fmt::Display for ExCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAnEditorCommand(c) => write!(f, "Not an editor command: {c}"),
+            Self::ArgumentRequired(c) => write!(f, "Argument required: {c}"),
+            Self::TrailingCharacters(c) => write!(f, "Trailing characters: {c}"),
+            Self::InvalidArgument(c) => write!(f, "Invalid argument: {c}"),
+            Self::NoWriteSinceLastChange => {
+                write!(f, "No write since last change (add ! to override)")
+            }
+            Self::NoFileName => write!(f, "No file name"),
+            Self::FileExists => write!(f, "File exists (add ! to override)"),
+        }
+    }
+}
+
+impl Error for ExCommandError {}
+
+impl ExCommand {
+    //Grammar: name[!] [argument]
+    pub fn parse(input: &str) -> Result<Self, ExCommandError> {
+        let input = input.trim();
+        let name_len = input
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(input.len());
+        let (name, rest) = input.split_at(name_len);
+        let (force, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let argument = rest.trim();
+        if !rest.is_empty() && !rest.starts_with(' ') && !argument.is_empty() {
+            return Err(ExCommandError::TrailingCharacters(input.to_string()));
+        }
+        let path = (!argument.is_empty()).then(|| PathBuf::from(argument));
+        let argument_required = || {
+            if argument.is_empty() {
+                Err(ExCommandError::ArgumentRequired(input.to_string()))
+            } else {
+                Ok(argument)
+            }
+        };
+        let tag = || {
+            let tag = argument_required()?;
+            if Tag::parse(tag).is_some() && !tag.contains(char::is_whitespace) {
+                Ok(tag.to_string())
+            } else {
+                Err(ExCommandError::InvalidArgument(input.to_string()))
+            }
+        };
+        let no_argument = |command: Self| {
+            if path.is_some() {
+                Err(ExCommandError::TrailingCharacters(input.to_string()))
+            } else {
+                Ok(command)
+            }
+        };
+
+        match name {
+            "w" | "write" => Ok(Self::Write { path, force }),
+            "q" | "quit" => no_argument(Self::Quit { force }),
+            "wq" | "x" | "xit" => no_argument(Self::WriteQuit { force }),
+            "e" | "edit" => Ok(Self::Edit { path, force }),
+            "sav" | "saveas" => path
+                .map(|path| Self::SaveAs { path, force })
+                .ok_or_else(|| ExCommandError::ArgumentRequired(input.to_string())),
+            "tag" => tag().map(Self::Tag),
+            "untag" => tag().map(Self::Untag),
+            //A date, +N days from today or none to remove it
+            "due" => match argument_required()? {
+                "none" => Ok(Self::Due(None)),
+                days if days.starts_with('+') => days[1..]
+                    .parse()
+                    .ok()
+                    .and_then(|days| Date::today().add_days(days))
+                    .map(|date| Self::Due(Some(date)))
+                    .ok_or_else(|| ExCommandError::InvalidArgument(input.to_string())),
+                date => date
+                    .parse()
+                    .map(|date| Self::Due(Some(date)))
+                    .map_err(|_| ExCommandError::InvalidArgument(input.to_string())),
+            },
+            "archive" => no_argument(Self::Archive),
+            "filter" if argument.is_empty() => Ok(Self::Filter(None)),
+            "filter" => Query::parse(argument)
+                .map(|query| Self::Filter(Some(query)))
+                .map_err(|e| ExCommandError::InvalidArgument(e.term)),
+            "sor" | "sort" => SortKey::parse_list(argument)
+                .map(|keys| Self::Sort {
+                    keys,
+                    reverse: force,
+                })
+                .map_err(ExCommandError::InvalidArgument),
+            "noh" | "nohlsearch" => no_argument(Self::NoHighlight),
+            "set" => Setting::parse(argument_required()?)
+                .map(Self::Set)
+                .ok_or_else(|| ExCommandError::InvalidArgument(input.to_string())),
+            _ => Err(ExCommandError::NotAnEditorCommand(input.to_string())),
+        }
+    }
+}
+
+//Text typed after ':' (or the '/' and '?' of a search) with its own cursor and history.
+//The cursor is a byte offset, it moves over whole graphemes.
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    text: String,
+    cursor: usize,
+    history: Vec<String>,
+    history_idx: Option<usize>,
+}
+
+impl CommandLine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+        self.history_idx = None;
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    fn prev_len(&self) -> Option<usize> {
+        self.text[..self.cursor]
+            .graphemes(true)
+            .next_back()
+            .map(str::len)
+    }
+
+    fn next_len(&self) -> Option<usize> {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map(str::len)
+    }
+
+    //Returns false if there was nothing to delete
+    pub fn backspace(&mut self) -> bool {
+        if let Some(len) = self.prev_len() {
+            self.cursor -= len;
+            self.text.replace_range(self.cursor..self.cursor + len, "");
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if let Some(len) = self.next_len() {
+            self.text.replace_range(self.cursor..self.cursor + len, "");
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if let Some(len) = self.prev_len() {
+            self.cursor -= len;
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(len) = self.next_len() {
+            self.cursor += len;
+        }
+    }
+
+    pub fn history_prev(&mut self) {
+        let idx = match self.history_idx {
+            Some(0) => return,
+            Some(idx) => idx - 1,
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        self.set_from_history(Some(idx));
+    }
+
+    pub fn history_next(&mut self) {
+        match self.history_idx {
+            Some(idx) if idx + 1 < self.history.len() => self.set_from_history(Some(idx + 1)),
+            Some(_) => self.set_from_history(None),
+            None => {}
+        }
+    }
+
+    fn set_from_history(&mut self, idx: Option<usize>) {
+        self.history_idx = idx;
+        self.text = idx.map(|i| self.history[i].clone()).unwrap_or_default();
+        self.cursor = self.text.len();
+    }
+
+    //Take the typed text, remembering it in the history
+    pub fn submit(&mut self) -> String {
+        let text = std::mem::take(&mut self.text);
+        if !text.trim().is_empty() && self.history.last() != Some(&text) {
+            self.history.push(text.clone());
+        }
+        self.clear();
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CommandLine, ExCommand, ExCommandError, Setting};
+    use crate::{date::Date, query::Query, sort::SortKey};
+    use std::path::PathBuf;
+
+    macro_rules! parse_ex_command_test_wrapper {
+        ($name: ident,$input: expr, $out: expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(ExCommand::parse($input), $out);
+            }
+        };
+    }
+
+    parse_ex_command_test_wrapper!(
+        parse_ex_w,
+        "w",
+        Ok(ExCommand::Write {
+            path: None,
+            force: false
+        })
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_w_path,
+        "w todo.txt",
+        Ok(ExCommand::Write {
+            path: Some(PathBuf::from("todo.txt")),
+            force: false
+        })
+    );
+    parse_ex_command_test_wrapper!(parse_ex_q, "q", Ok(ExCommand::Quit { force: false }));
+    parse_ex_command_test_wrapper!(
+        parse_ex_q_force,
+        " q! ",
+        Ok(ExCommand::Quit { force: true })
+    );
+    parse_ex_command_test_wrapper!(parse_ex_wq, "wq", Ok(ExCommand::WriteQuit { force: false }));
+    parse_ex_command_test_wrapper!(
+        parse_ex_x_force,
+        "x!",
+        Ok(ExCommand::WriteQuit { force: true })
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_e_force,
+        "e!",
+        Ok(ExCommand::Edit {
+            path: None,
+            force: true
+        })
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_edit_path,
+        "edit other.txt",
+        Ok(ExCommand::Edit {
+            path: Some(PathBuf::from("other.txt")),
+            force: false
+        })
+    );
+    parse_ex_command_test_wrapper!(parse_ex_noh, "noh", Ok(ExCommand::NoHighlight));
+    parse_ex_command_test_wrapper!(
+        parse_ex_sort,
+        "sort! due -pri",
+        Ok(ExCommand::Sort {
+            keys: SortKey::parse_list("due -pri").unwrap(),
+            reverse: true
+        })
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_sort_invalid,
+        "sort due size",
+        Err(ExCommandError::InvalidArgument(String::from("size")))
+    );
+    parse_ex_command_test_wrapper!(parse_ex_filter_clear, "filter", Ok(ExCommand::Filter(None)));
+    parse_ex_command_test_wrapper!(
+        parse_ex_filter,
+        "filter is:open +backend",
+        Ok(ExCommand::Filter(Some(
+            Query::parse("is:open +backend").unwrap()
+        )))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_filter_invalid,
+        "filter +backend pri:a",
+        Err(ExCommandError::InvalidArgument(String::from("pri:a")))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_saveas,
+        "saveas new.txt",
+        Ok(ExCommand::SaveAs {
+            path: PathBuf::from("new.txt"),
+            force: false
+        })
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_saveas_force,
+        "sav! new.txt",
+        Ok(ExCommand::SaveAs {
+            path: PathBuf::from("new.txt"),
+            force: true
+        })
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_saveas_no_arg,
+        "sav",
+        Err(ExCommandError::ArgumentRequired(String::from("sav")))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_q_arg,
+        "q now",
+        Err(ExCommandError::TrailingCharacters(String::from("q now")))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_set_wrap,
+        "set wrap",
+        Ok(ExCommand::Set(Setting::Wrap(true)))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_set_nowrap,
+        "set nowrap",
+        Ok(ExCommand::Set(Setting::Wrap(false)))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_set_unknown,
+        "set foo",
+        Err(ExCommandError::InvalidArgument(String::from("set foo")))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_unknown,
+        "foo",
+        Err(ExCommandError::NotAnEditorCommand(String::from("foo")))
+    );
+
+    parse_ex_command_test_wrapper!(
+        parse_ex_tag,
+        "tag +project",
+        Ok(ExCommand::Tag(String::from("+project")))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_untag,
+        "untag @home",
+        Ok(ExCommand::Untag(String::from("@home")))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_tag_invalid,
+        "tag project",
+        Err(ExCommandError::InvalidArgument(String::from("tag project")))
+    );
+    parse_ex_command_test_wrapper!(
+        parse_ex_due,
+        "due 2024-03-01",
+        Ok(ExCommand::Due(Date::new(2024, 3, 1)))
+    );
+    parse_ex_command_test_wrapper!(parse_ex_due_none, "due none", Ok(ExCommand::Due(None)));
+    parse_ex_command_test_wrapper!(
+        parse_ex_due_invalid,
+        "due tomorrow",
+        Err(ExCommandError::InvalidArgument(String::from(
+            "due tomorrow"
+        )))
+    );
+    parse_ex_command_test_wrapper!(parse_ex_archive, "archive", Ok(ExCommand::Archive));
+
+    #[test]
+    fn parse_ex_due_relative() {
+        assert_eq!(
+            ExCommand::parse("due +2"),
+            Ok(ExCommand::Due(Date::today().add_days(2)))
+        );
+        //Past 9999-12-31, and past what fits in the number of days
+        for days in ["3660000", "9223372036854775807"] {
+            let input = format!("due +{days}");
+            assert_eq!(
+                ExCommand::parse(&input),
+                Err(ExCommandError::InvalidArgument(input.clone()))
+            );
+        }
+    }
+
+    #[test]
+    fn editing() {
+        let mut cl = CommandLine::new();
+        for c in "wq".chars() {
+            cl.insert(c);
+        }
+        cl.move_left();
+        cl.insert('!');
+        assert_eq!(cl.text(), "w!q");
+        cl.delete();
+        assert_eq!(cl.text(), "w!");
+        assert!(cl.backspace());
+        assert!(cl.backspace());
+        assert!(!cl.backspace());
+
+        //An accented letter made of two chars is a single step
+        for c in "tag e\u{301}t\u{e9}".chars() {
+            cl.insert(c);
+        }
+        cl.move_left();
+        cl.move_left();
+        assert!(cl.backspace());
+        assert_eq!(cl.text(), "tag t\u{e9}");
+        cl.delete();
+        assert_eq!(cl.text(), "tag \u{e9}");
+    }
+
+    #[test]
+    fn history() {
+        let mut cl = CommandLine::new();
+        cl.insert('w');
+        assert_eq!(cl.submit(), "w");
+        cl.insert('q');
+        assert_eq!(cl.submit(), "q");
+
+        cl.history_prev();
+        assert_eq!(cl.text(), "q");
+        cl.history_prev();
+        assert_eq!(cl.text(), "w");
+        cl.history_prev();
+        assert_eq!(cl.text(), "w");
+        cl.history_next();
+        assert_eq!(cl.text(), "q");
+        cl.history_next();
+        assert_eq!(cl.text(), "");
+    }
+}
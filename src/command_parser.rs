@@ -1,4 +1,4 @@
-use crate::char_parser::Character;
+use crate::{char_parser::Character, registers::Registers};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum NormalModeCommand {
@@ -6,14 +6,12 @@ pub enum NormalModeCommand {
     MoveUp,
     MoveLeft,
     MoveRight,
-    Quit,
+    Interrupt, //Ctrl-C, only tells how to quit
     MoveToBottom,
     MoveToTop,
     GoTo, //Intended for 'g'
     EnterInsertMode,
     Append,
-    Delete,
-    DeleteLine,
     AddLineBottom,
     AddLineTop,
     NextWord,
@@ -21,12 +19,138 @@ pub enum NormalModeCommand {
     ToBeg,
     ToEnd,
     DeleteChar,
+    Replace,           //Intended for 'r'
+    ReplaceChar(char), //The count characters from the cursor become the char
+    EnterReplaceMode,
+    ToggleCase,
+    JoinLines,
+    EnterCommandMode,
+    //Todo commands, the count is the number of items, the number of steps for priorities
+    //and the number of days from today for the due date
+    ToggleDone,
+    RaisePriority,
+    LowerPriority,
+    SetDueDate,
+    AddTag,
+    RemoveTag,
+    Archive,
+    Sort, //Opens :sort for the items, all of them without a count
+    Undo,
+    Redo,
+    Repeat, //Intended for '.', does the last change again
+    Record, //Intended for 'q'
+    RecordMacro(char),
+    StopRecording,
+    Play,            //Intended for '@'
+    PlayMacro(char), //'@' plays the last one played again
+    SelectRegister,  //Intended for '"'
+    Operate(Operation),
+    EnterVisualMode,
+    EnterVisualLineMode,
+    Put,
+    PutBefore,
+    Search { forward: bool }, //Intended for '/' and '?', opens the prompt
+    SearchNext,
+    SearchPrev,
+    Fold, //Intended for 'z'
+    ToggleFold,
+    OpenFold,
+    CloseFold,
+    OpenAllFolds,
+    CloseAllFolds,
+    Mark, //Intended for 'm'
+    SetMark(char),
+    Jump { linewise: bool }, //Intended for '\'' and '`'
+    JumpToMark { name: char, linewise: bool },
+    JumpBack,    //Ctrl-O, to where the last jump started
+    JumpForward, //Ctrl-I, the other way
+}
+
+impl NormalModeCommand {
+    //Whether it changes the content, so . can repeat it
+    pub fn is_change(self) -> bool {
+        match self {
+            Self::Operate(operation) => operation.operator != Operator::Yank,
+            Self::DeleteChar
+            | Self::ReplaceChar(_)
+            | Self::EnterReplaceMode
+            | Self::ToggleCase
+            | Self::JoinLines
+            | Self::EnterInsertMode
+            | Self::Append
+            | Self::AddLineBottom
+            | Self::AddLineTop
+            | Self::Put
+            | Self::PutBefore
+            | Self::ToggleDone
+            | Self::RaisePriority
+            | Self::LowerPriority
+            | Self::SetDueDate
+            | Self::AddTag
+            | Self::RemoveTag
+            | Self::Archive
+            | Self::Sort => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+    ToggleCase,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Motion {
+    NextWord,
+    PrevWord,
+    ToEnd,
+    ToBeg,
+    Left,
+    Right,
+    Down,
+    Up,
+    ToBottom,
+    ToTop,
+    Line, //The operator typed twice, e.g. dd
+    //Exclusive like w, the pattern is typed after the motion
+    Search { forward: bool },
+    SearchNext, //n, in the direction of the last search
+    SearchPrev, //N, the other way
+    //'a goes to the line of the mark, `a to the exact position
+    Mark { name: char, linewise: bool },
+}
+
+//An operator applied over the range a motion moves across
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Operation {
+    pub operator: Operator,
+    pub motion: Motion,
+    //The counts of the operator and the motion multiplied, None if neither was given
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum VisualModeCommand {
+    Visual,     //'v', switches between the visual modes or leaves them
+    VisualLine, //'V'
+    Exit,
+    SwapEnds,
+    Operate(Operator),
+    //Motions, todo commands and the rest of what's shared with normal mode
+    Normal(NormalModeCommand),
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum InsertModeCommand {
     EnterNormalMode,
     Insert(char),
+    NewLine, //Splits the line at the cursor
     Delete,
     Backspace,
     MoveDown,
@@ -35,10 +159,27 @@ pub enum InsertModeCommand {
     MoveRight,
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CommandModeCommand {
+    EnterNormalMode,
+    Execute,
+    Insert(char),
+    Delete,
+    Backspace,
+    MoveLeft,
+    MoveRight,
+    HistoryPrev,
+    HistoryNext,
+}
+
 #[derive(Debug)]
 pub struct CommandParser {
     command_buffer: Vec<NormalModeCommand>,
     nr_prefix: Option<usize>,
+    register: Option<char>,
+    //Operator waiting for a motion, with the count typed before it
+    pending_operator: Option<(Operator, Option<usize>)>,
+    recording: bool, //A macro is being recorded, q stops it
 }
 
 impl CommandParser {
@@ -46,10 +187,17 @@ impl CommandParser {
         Self {
             command_buffer: Vec::new(),
             nr_prefix: None,
+            register: None,
+            pending_operator: None,
+            recording: false,
         }
     }
 
-    #[cfg(test)]
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    //Also gives a repeated change its count
     pub fn set_nr_prefix(&mut self, nr_prefix: Option<usize>) {
         self.nr_prefix = nr_prefix;
     }
@@ -75,8 +223,9 @@ impl CommandParser {
             Character::Esc => Some(InsertModeCommand::EnterNormalMode),
             //Backspace
             Character::Backspace => Some(InsertModeCommand::Backspace),
+            Character::Enter => Some(InsertModeCommand::NewLine),
             Character::Display(c) => {
-                if c.is_ascii_graphic() || c == ' ' {
+                if !c.is_control() {
                     Some(InsertModeCommand::Insert(c))
                 } else {
                     None
@@ -92,69 +241,283 @@ impl CommandParser {
             Character::ArrowRight => Some(InsertModeCommand::MoveRight),
             //Delete
             Character::Delete => Some(InsertModeCommand::Delete),
-            // _ => None,
+            _ => None,
+        }
+    }
+
+    pub fn parse_command_mode_command(&self, c: Character) -> Option<CommandModeCommand> {
+        match c {
+            Character::Esc => Some(CommandModeCommand::EnterNormalMode),
+            Character::Enter => Some(CommandModeCommand::Execute),
+            Character::Backspace => Some(CommandModeCommand::Backspace),
+            Character::Delete => Some(CommandModeCommand::Delete),
+            Character::Display(c) => Some(CommandModeCommand::Insert(c)),
+            Character::ArrowUp => Some(CommandModeCommand::HistoryPrev),
+            Character::ArrowDown => Some(CommandModeCommand::HistoryNext),
+            Character::ArrowLeft => Some(CommandModeCommand::MoveLeft),
+            Character::ArrowRight => Some(CommandModeCommand::MoveRight),
+            Character::Ctrl('c') => Some(CommandModeCommand::EnterNormalMode),
+            _ => None,
+        }
+    }
+
+    //Waits for a motion, or completes a linewise operation when typed twice
+    fn operator(&mut self, operator: Operator) -> Option<NormalModeCommand> {
+        match self.pending_operator {
+            Some((pending, _)) if pending == operator => self.motion(Motion::Line),
+            Some(_) => self.cancel(),
+            None => {
+                self.pending_operator = Some((operator, self.nr_prefix.take()));
+                None
+            }
+        }
+    }
+
+    fn change(&mut self, motion: Motion) -> Option<NormalModeCommand> {
+        self.pending_operator = Some((Operator::Change, None));
+        self.motion(motion)
+    }
+
+    //Completes the pending operator, or just moves the cursor without one
+    fn motion(&mut self, motion: Motion) -> Option<NormalModeCommand> {
+        match self.pending_operator.take() {
+            Some((operator, operator_count)) => {
+                let count = match (operator_count, self.nr_prefix.take()) {
+                    (None, None) => None,
+                    (a, b) => Some(a.unwrap_or(1) * b.unwrap_or(1)),
+                };
+                Some(NormalModeCommand::Operate(Operation {
+                    operator,
+                    motion,
+                    count,
+                }))
+            }
+            None => Some(match motion {
+                Motion::NextWord => NormalModeCommand::NextWord,
+                Motion::PrevWord => NormalModeCommand::PrevWord,
+                Motion::ToEnd => NormalModeCommand::ToEnd,
+                Motion::ToBeg => NormalModeCommand::ToBeg,
+                Motion::Left => NormalModeCommand::MoveLeft,
+                Motion::Right => NormalModeCommand::MoveRight,
+                Motion::Down => NormalModeCommand::MoveDown,
+                Motion::Up => NormalModeCommand::MoveUp,
+                Motion::ToBottom => NormalModeCommand::MoveToBottom,
+                Motion::ToTop | Motion::Line => NormalModeCommand::MoveToTop,
+                Motion::Search { forward } => NormalModeCommand::Search { forward },
+                Motion::SearchNext => NormalModeCommand::SearchNext,
+                Motion::SearchPrev => NormalModeCommand::SearchPrev,
+                Motion::Mark { name, linewise } => NormalModeCommand::JumpToMark { name, linewise },
+            }),
         }
     }
 
     pub fn parse_normal_mode_command(&mut self, c: Character) -> Option<NormalModeCommand> {
+        //Every prefix takes exactly one more key, whatever it is
+        let prefix = std::mem::take(&mut self.command_buffer);
+
+        if prefix == vec![NormalModeCommand::SelectRegister] {
+            if let Character::Display(name) = c {
+                if Registers::is_valid_name(name) {
+                    self.register = Some(name);
+                }
+            }
+            return None;
+        }
+        if prefix == vec![NormalModeCommand::GoTo] {
+            return match c {
+                Character::Display('g') => self.motion(Motion::ToTop),
+                _ if self.pending_operator.is_some() => self.cancel(),
+                Character::Display('d') => Some(NormalModeCommand::SetDueDate),
+                Character::Display('t') => Some(NormalModeCommand::AddTag),
+                Character::Display('T') => Some(NormalModeCommand::RemoveTag),
+                Character::Display('a') => Some(NormalModeCommand::Archive),
+                Character::Display('s') => Some(NormalModeCommand::Sort),
+                _ => None,
+            };
+        }
+        if prefix == vec![NormalModeCommand::Record] {
+            return match c {
+                Character::Display(name) if name.is_ascii_alphabetic() => {
+                    Some(NormalModeCommand::RecordMacro(name))
+                }
+                _ => self.cancel(),
+            };
+        }
+        if prefix == vec![NormalModeCommand::Play] {
+            return match c {
+                Character::Display(name) if name.is_ascii_alphabetic() || name == '@' => {
+                    Some(NormalModeCommand::PlayMacro(name))
+                }
+                _ => self.cancel(),
+            };
+        }
+        if prefix == vec![NormalModeCommand::Mark] {
+            return match c {
+                Character::Display(name) if name.is_ascii_lowercase() => {
+                    Some(NormalModeCommand::SetMark(name))
+                }
+                _ => self.cancel(),
+            };
+        }
+        if let [NormalModeCommand::Jump { linewise }] = prefix[..] {
+            return match c {
+                Character::Display(name) if name.is_ascii_lowercase() => {
+                    self.motion(Motion::Mark { name, linewise })
+                }
+                _ => self.cancel(),
+            };
+        }
+        if prefix == vec![NormalModeCommand::Replace] {
+            return match c {
+                Character::Display(c) => Some(NormalModeCommand::ReplaceChar(c)),
+                _ => self.cancel(),
+            };
+        }
+        if prefix == vec![NormalModeCommand::Fold] {
+            return match c {
+                Character::Display('a') => Some(NormalModeCommand::ToggleFold),
+                Character::Display('o') => Some(NormalModeCommand::OpenFold),
+                Character::Display('c') => Some(NormalModeCommand::CloseFold),
+                Character::Display('R') => Some(NormalModeCommand::OpenAllFolds),
+                Character::Display('M') => Some(NormalModeCommand::CloseAllFolds),
+                _ => None,
+            };
+        }
+
         match c {
             //Esc
             Character::Esc => {
-                self.command_buffer.clear();
+                self.register = None;
+                self.cancel()
+            }
+            //A 0 that isn't part of a count is a motion
+            Character::Display(c @ '0'..='9') if c != '0' || self.nr_prefix.is_some() => {
+                self.handle_number_prefix(c)
+            }
+            Character::Display('g') => {
+                self.command_buffer.push(NormalModeCommand::GoTo);
+                None
+            }
+            Character::Display('d') => self.operator(Operator::Delete),
+            Character::Display('c') => self.operator(Operator::Change),
+            Character::Display('y') => self.operator(Operator::Yank),
+            Character::Display('>') => self.operator(Operator::Indent),
+            Character::Display('<') => self.operator(Operator::Outdent),
+            Character::ArrowDown | Character::Display('j') => self.motion(Motion::Down),
+            Character::ArrowUp | Character::Display('k') => self.motion(Motion::Up),
+            Character::ArrowLeft | Character::Display('h') => self.motion(Motion::Left),
+            Character::ArrowRight | Character::Display('l') => self.motion(Motion::Right),
+            Character::Display('w') => self.motion(Motion::NextWord),
+            Character::Display('b') => self.motion(Motion::PrevWord),
+            Character::End | Character::Display('$') => self.motion(Motion::ToEnd),
+            Character::Home | Character::Display('0') => self.motion(Motion::ToBeg),
+            Character::Display('G') => self.motion(Motion::ToBottom),
+            Character::Display('/') => self.motion(Motion::Search { forward: true }),
+            Character::Display('?') => self.motion(Motion::Search { forward: false }),
+            Character::Display('n') => self.motion(Motion::SearchNext),
+            Character::Display('N') => self.motion(Motion::SearchPrev),
+            Character::Display(c @ ('\'' | '`')) => {
+                self.command_buffer.push(NormalModeCommand::Jump {
+                    linewise: c == '\'',
+                });
+                None
+            }
+            //Anything else cancels the pending operator
+            _ if self.pending_operator.is_some() => self.cancel(),
+            Character::Display('"') => {
+                self.command_buffer.push(NormalModeCommand::SelectRegister);
                 None
             }
             //Ctrl-C
-            Character::Display('\u{3}' | 'q') => Some(NormalModeCommand::Quit),
-            Character::ArrowDown | Character::Display('j') => Some(NormalModeCommand::MoveDown),
-            Character::ArrowUp | Character::Display('k') => Some(NormalModeCommand::MoveUp),
-            Character::ArrowLeft | Character::Display('h') => Some(NormalModeCommand::MoveLeft),
-            Character::ArrowRight | Character::Display('l') => Some(NormalModeCommand::MoveRight),
-            Character::Display('G') => Some(NormalModeCommand::MoveToBottom),
-            Character::Display('g') => {
-                if self.command_buffer == vec![NormalModeCommand::GoTo] {
-                    self.command_buffer.clear();
-                    Some(NormalModeCommand::MoveToTop)
-                } else {
-                    // if let Some(NormalModeCommand::NumberPrefix(o)) = self.command_buffer.first().cloned() {
-                    //     if self.command_buffer.get(1) == Some(&NormalModeCommand::GoTo) {
-                    //         self.command_buffer.clear();
-                    //         self.scroll_beg = o as usize;
-                    //         self.scroll_end = self.scroll_beg + self.term_columns as usize;
-                    //         self.cursor.y = 0;
-                    //     }
-                    // }
-                    self.command_buffer.push(NormalModeCommand::GoTo);
-                    None
-                }
+            Character::Display('\u{3}') | Character::Ctrl('c') => {
+                Some(NormalModeCommand::Interrupt)
             }
-            Character::Display('0'..='9') => {
-                if let Character::Display(c) = c {
-                    self.handle_number_prefix(c)
-                } else {
-                    None
-                }
+            Character::Display('q') if self.recording => Some(NormalModeCommand::StopRecording),
+            Character::Display('q') => {
+                self.command_buffer.push(NormalModeCommand::Record);
+                None
+            }
+            Character::Display('@') => {
+                self.command_buffer.push(NormalModeCommand::Play);
+                None
             }
+            //S is cc, C is c$ and s is cl
+            Character::Display('S') => self.change(Motion::Line),
+            Character::Display('C') => self.change(Motion::ToEnd),
+            Character::Display('s') => self.change(Motion::Right),
+            Character::Display('r') => {
+                self.command_buffer.push(NormalModeCommand::Replace);
+                None
+            }
+            Character::Display('R') => Some(NormalModeCommand::EnterReplaceMode),
+            Character::Display('~') => Some(NormalModeCommand::ToggleCase),
             Character::Display('i') => Some(NormalModeCommand::EnterInsertMode),
             Character::Display('a') => Some(NormalModeCommand::Append),
-            Character::Display('d') => {
-                if self.command_buffer == vec![NormalModeCommand::Delete] {
-                    self.command_buffer.clear();
-                    Some(NormalModeCommand::DeleteLine)
-                } else {
-                    self.command_buffer.push(NormalModeCommand::Delete);
-                    None
-                }
-            }
+            Character::Display('p') => Some(NormalModeCommand::Put),
+            Character::Display('P') => Some(NormalModeCommand::PutBefore),
             Character::Display('o') => Some(NormalModeCommand::AddLineBottom),
             Character::Display('O') => Some(NormalModeCommand::AddLineTop),
-            Character::Display('w') => Some(NormalModeCommand::NextWord),
-            Character::Display('b') => Some(NormalModeCommand::PrevWord),
-            Character::Display('$') => Some(NormalModeCommand::ToEnd),
             Character::Display('x') => Some(NormalModeCommand::DeleteChar),
+            Character::Display('J') => Some(NormalModeCommand::JoinLines),
+            Character::Display(':') => Some(NormalModeCommand::EnterCommandMode),
+            Character::Display('t') => Some(NormalModeCommand::ToggleDone),
+            Character::Display('+') => Some(NormalModeCommand::RaisePriority),
+            Character::Display('-') => Some(NormalModeCommand::LowerPriority),
+            Character::Display('v') => Some(NormalModeCommand::EnterVisualMode),
+            Character::Display('V') => Some(NormalModeCommand::EnterVisualLineMode),
+            Character::Display('u') => Some(NormalModeCommand::Undo),
+            Character::Display('.') => Some(NormalModeCommand::Repeat),
+            Character::Ctrl('r') => Some(NormalModeCommand::Redo),
+            Character::Ctrl('o') => Some(NormalModeCommand::JumpBack),
+            //Terminals send Ctrl-I as Tab
+            Character::Tab | Character::Ctrl('i') => Some(NormalModeCommand::JumpForward),
+            Character::Display('m') => {
+                self.command_buffer.push(NormalModeCommand::Mark);
+                None
+            }
+            Character::Display('z') => {
+                self.command_buffer.push(NormalModeCommand::Fold);
+                None
+            }
             _ => None,
         }
     }
 
+    //Operators apply to the selection right away, everything else is parsed like in normal mode
+    pub fn parse_visual_mode_command(&mut self, c: Character) -> Option<VisualModeCommand> {
+        if self.command_buffer.is_empty() {
+            let command = match c {
+                Character::Esc => Some(VisualModeCommand::Exit),
+                Character::Display('v') => Some(VisualModeCommand::Visual),
+                Character::Display('V') => Some(VisualModeCommand::VisualLine),
+                Character::Display('o') => Some(VisualModeCommand::SwapEnds),
+                Character::Display('d' | 'x') => Some(VisualModeCommand::Operate(Operator::Delete)),
+                Character::Display('c' | 's') => Some(VisualModeCommand::Operate(Operator::Change)),
+                Character::Display('y') => Some(VisualModeCommand::Operate(Operator::Yank)),
+                Character::Display('>') => Some(VisualModeCommand::Operate(Operator::Indent)),
+                Character::Display('<') => Some(VisualModeCommand::Operate(Operator::Outdent)),
+                Character::Display('~') => Some(VisualModeCommand::Operate(Operator::ToggleCase)),
+                _ => None,
+            };
+            if command.is_some() {
+                if matches!(command, Some(VisualModeCommand::Operate(_))) {
+                    self.nr_prefix = None;
+                }
+                return command;
+            }
+        }
+        self.parse_normal_mode_command(c)
+            .map(VisualModeCommand::Normal)
+    }
+
+    //Drops whatever was typed so far
+    fn cancel(&mut self) -> Option<NormalModeCommand> {
+        self.command_buffer.clear();
+        self.pending_operator = None;
+        self.nr_prefix = None;
+        None
+    }
+
     pub fn nr_prefix(&self) -> Option<usize> {
         self.nr_prefix
     }
@@ -162,13 +525,30 @@ impl CommandParser {
     pub fn clear_nr_prefix(&mut self) {
         self.nr_prefix = None;
     }
+
+    //The register selected with '"', cleared by the editor after every command
+    pub fn register(&self) -> Option<char> {
+        self.register
+    }
+
+    pub fn clear_register(&mut self) {
+        self.register = None;
+    }
+
+    //Gives an operation that waited for a search prompt its register back
+    pub fn set_register(&mut self, register: Option<char>) {
+        self.register = register;
+    }
 }
 
 #[cfg(test)]
 mod test {
 
     use crate::char_parser::Character;
-    use crate::command_parser::{CommandParser, InsertModeCommand, NormalModeCommand};
+    use crate::command_parser::{
+        CommandModeCommand, CommandParser, InsertModeCommand, Motion, NormalModeCommand, Operation,
+        Operator, VisualModeCommand,
+    };
 
     macro_rules! parse_insert_command_test_wrapper {
         ($name: ident,$c: expr, $out: expr) => {
@@ -186,6 +566,12 @@ mod test {
         Some(InsertModeCommand::EnterNormalMode)
     );
 
+    parse_insert_command_test_wrapper!(
+        parse_insert_command_enter,
+        Character::Enter,
+        Some(InsertModeCommand::NewLine)
+    );
+
     parse_insert_command_test_wrapper!(
         parse_insert_command_delete,
         Character::Delete,
@@ -216,6 +602,34 @@ mod test {
         Some(InsertModeCommand::MoveRight)
     );
 
+    macro_rules! parse_command_mode_command_test_wrapper {
+        ($name: ident,$c: expr, $out: expr) => {
+            #[test]
+            fn $name() {
+                let cp = CommandParser::new();
+                assert_eq!(cp.parse_command_mode_command($c), $out);
+            }
+        };
+    }
+
+    parse_command_mode_command_test_wrapper!(
+        parse_command_mode_command_enter,
+        Character::Enter,
+        Some(CommandModeCommand::Execute)
+    );
+
+    parse_command_mode_command_test_wrapper!(
+        parse_command_mode_command_esc,
+        Character::Esc,
+        Some(CommandModeCommand::EnterNormalMode)
+    );
+
+    parse_command_mode_command_test_wrapper!(
+        parse_command_mode_command_arr_up,
+        Character::ArrowUp,
+        Some(CommandModeCommand::HistoryPrev)
+    );
+
     macro_rules! parse_normal_command_test_wrapper {
         ($name: ident,$c: expr, $out: expr) => {
             #[test]
@@ -227,15 +641,15 @@ mod test {
     }
 
     //We want to keep theese as separate tests TK
-    parse_normal_command_test_wrapper!(
-        parse_normal_command_q,
-        Character::Display('q'),
-        Some(NormalModeCommand::Quit)
-    );
     parse_normal_command_test_wrapper!(
         parse_normal_command_ctrl_c,
         Character::Display('\u{3}'),
-        Some(NormalModeCommand::Quit)
+        Some(NormalModeCommand::Interrupt)
+    );
+    parse_normal_command_test_wrapper!(
+        parse_normal_command_join,
+        Character::Display('J'),
+        Some(NormalModeCommand::JoinLines)
     );
     parse_normal_command_test_wrapper!(
         parse_normal_command_j,
@@ -257,6 +671,36 @@ mod test {
         Character::Display('l'),
         Some(NormalModeCommand::MoveRight)
     );
+    parse_normal_command_test_wrapper!(
+        parse_normal_command_colon,
+        Character::Display(':'),
+        Some(NormalModeCommand::EnterCommandMode)
+    );
+    parse_normal_command_test_wrapper!(
+        parse_normal_command_t,
+        Character::Display('t'),
+        Some(NormalModeCommand::ToggleDone)
+    );
+    parse_normal_command_test_wrapper!(
+        parse_normal_command_plus,
+        Character::Display('+'),
+        Some(NormalModeCommand::RaisePriority)
+    );
+    parse_normal_command_test_wrapper!(
+        parse_normal_command_minus,
+        Character::Display('-'),
+        Some(NormalModeCommand::LowerPriority)
+    );
+    parse_normal_command_test_wrapper!(
+        parse_normal_command_u,
+        Character::Display('u'),
+        Some(NormalModeCommand::Undo)
+    );
+    parse_normal_command_test_wrapper!(
+        parse_normal_command_ctrl_r,
+        Character::Ctrl('r'),
+        Some(NormalModeCommand::Redo)
+    );
     parse_normal_command_test_wrapper!(
         parse_normal_command_capital_g,
         Character::Display('G'),
@@ -286,4 +730,306 @@ mod test {
         assert!(cp.command_buffer.is_empty());
         assert_eq!(cp.nr_prefix, None);
     }
+
+    #[test]
+    fn parse_command_g_prefix() {
+        let mut cp = CommandParser::new();
+        for (c, command) in [
+            ('d', NormalModeCommand::SetDueDate),
+            ('t', NormalModeCommand::AddTag),
+            ('T', NormalModeCommand::RemoveTag),
+            ('a', NormalModeCommand::Archive),
+            ('s', NormalModeCommand::Sort),
+        ] {
+            assert_eq!(cp.parse_normal_mode_command(Character::Display('g')), None);
+            assert_eq!(
+                cp.parse_normal_mode_command(Character::Display(c)),
+                Some(command)
+            );
+            assert!(cp.command_buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn parse_command_z_prefix() {
+        let mut cp = CommandParser::new();
+        for (c, command) in [
+            ('a', Some(NormalModeCommand::ToggleFold)),
+            ('o', Some(NormalModeCommand::OpenFold)),
+            ('c', Some(NormalModeCommand::CloseFold)),
+            ('R', Some(NormalModeCommand::OpenAllFolds)),
+            ('M', Some(NormalModeCommand::CloseAllFolds)),
+            ('x', None),
+        ] {
+            assert_eq!(cp.parse_normal_mode_command(Character::Display('z')), None);
+            assert_eq!(cp.parse_normal_mode_command(Character::Display(c)), command);
+            assert!(cp.command_buffer.is_empty());
+        }
+        //It isn't a motion
+        assert_eq!(parse_keys(&mut cp, "dz"), None);
+        assert_eq!(cp.pending_operator, None);
+    }
+
+    fn parse_keys(cp: &mut CommandParser, keys: &str) -> Option<NormalModeCommand> {
+        let mut command = None;
+        for c in keys.chars() {
+            command = cp.parse_normal_mode_command(Character::Display(c));
+        }
+        command
+    }
+
+    #[test]
+    fn parse_command_operator() {
+        let mut cp = CommandParser::new();
+        for (keys, operator, motion, count) in [
+            ("dd", Operator::Delete, Motion::Line, None),
+            ("3yy", Operator::Yank, Motion::Line, Some(3)),
+            ("2d3w", Operator::Delete, Motion::NextWord, Some(6)),
+            ("c2b", Operator::Change, Motion::PrevWord, Some(2)),
+            ("d$", Operator::Delete, Motion::ToEnd, None),
+            ("y0", Operator::Yank, Motion::ToBeg, None),
+            ("10>j", Operator::Indent, Motion::Down, Some(10)),
+            ("<k", Operator::Outdent, Motion::Up, None),
+            ("dG", Operator::Delete, Motion::ToBottom, None),
+            ("d5G", Operator::Delete, Motion::ToBottom, Some(5)),
+            ("ygg", Operator::Yank, Motion::ToTop, None),
+            (
+                "d/",
+                Operator::Delete,
+                Motion::Search { forward: true },
+                None,
+            ),
+            (
+                "2c?",
+                Operator::Change,
+                Motion::Search { forward: false },
+                Some(2),
+            ),
+            ("y3n", Operator::Yank, Motion::SearchNext, Some(3)),
+            ("dN", Operator::Delete, Motion::SearchPrev, None),
+            ("d2l", Operator::Delete, Motion::Right, Some(2)),
+            ("ch", Operator::Change, Motion::Left, None),
+            //The shorthands for c
+            ("3S", Operator::Change, Motion::Line, Some(3)),
+            ("C", Operator::Change, Motion::ToEnd, None),
+            ("2s", Operator::Change, Motion::Right, Some(2)),
+            (
+                "d'a",
+                Operator::Delete,
+                Motion::Mark {
+                    name: 'a',
+                    linewise: true,
+                },
+                None,
+            ),
+            (
+                "y`b",
+                Operator::Yank,
+                Motion::Mark {
+                    name: 'b',
+                    linewise: false,
+                },
+                None,
+            ),
+        ] {
+            assert_eq!(
+                parse_keys(&mut cp, keys),
+                Some(NormalModeCommand::Operate(Operation {
+                    operator,
+                    motion,
+                    count
+                })),
+                "{keys}"
+            );
+            assert_eq!(cp.nr_prefix(), None);
+        }
+
+        //Without an operator motions just move
+        assert_eq!(parse_keys(&mut cp, "w"), Some(NormalModeCommand::NextWord));
+        assert_eq!(
+            parse_keys(&mut cp, "?"),
+            Some(NormalModeCommand::Search { forward: false })
+        );
+        assert_eq!(
+            parse_keys(&mut cp, "N"),
+            Some(NormalModeCommand::SearchPrev)
+        );
+        //Anything that isn't a motion cancels the operator
+        assert_eq!(parse_keys(&mut cp, "2dx"), None);
+        assert_eq!(parse_keys(&mut cp, "dy"), None);
+        assert_eq!(parse_keys(&mut cp, "j"), Some(NormalModeCommand::MoveDown));
+        assert_eq!(cp.nr_prefix(), None);
+    }
+
+    #[test]
+    fn changes() {
+        let mut cp = CommandParser::new();
+        for (keys, is_change) in [
+            ("dw", true),
+            ("yy", false),
+            ("S", true),
+            ("ra", true),
+            ("t", true),
+            ("gd", true),
+            ("gt", true),
+            ("gT", true),
+            ("ga", true),
+            ("gs", true),
+            ("j", false),
+            ("u", false),
+            (".", false),
+        ] {
+            let command = parse_keys(&mut cp, keys).unwrap();
+            assert_eq!(command.is_change(), is_change, "{keys}");
+        }
+    }
+
+    #[test]
+    fn parse_command_macros() {
+        let mut cp = CommandParser::new();
+        assert_eq!(
+            parse_keys(&mut cp, "qa"),
+            Some(NormalModeCommand::RecordMacro('a'))
+        );
+        cp.set_recording(true);
+        assert_eq!(
+            parse_keys(&mut cp, "q"),
+            Some(NormalModeCommand::StopRecording)
+        );
+        cp.set_recording(false);
+        assert_eq!(parse_keys(&mut cp, "q1"), None);
+        assert!(cp.command_buffer.is_empty());
+        assert_eq!(
+            parse_keys(&mut cp, "@b"),
+            Some(NormalModeCommand::PlayMacro('b'))
+        );
+        assert_eq!(
+            parse_keys(&mut cp, "3@@"),
+            Some(NormalModeCommand::PlayMacro('@'))
+        );
+        assert_eq!(cp.nr_prefix(), Some(3));
+    }
+
+    #[test]
+    fn parse_command_marks() {
+        let mut cp = CommandParser::new();
+        assert_eq!(
+            parse_keys(&mut cp, "mx"),
+            Some(NormalModeCommand::SetMark('x'))
+        );
+        assert_eq!(
+            parse_keys(&mut cp, "'x"),
+            Some(NormalModeCommand::JumpToMark {
+                name: 'x',
+                linewise: true
+            })
+        );
+        assert_eq!(
+            parse_keys(&mut cp, "`x"),
+            Some(NormalModeCommand::JumpToMark {
+                name: 'x',
+                linewise: false
+            })
+        );
+        //Only lowercase letters name marks
+        assert_eq!(parse_keys(&mut cp, "mA"), None);
+        assert_eq!(parse_keys(&mut cp, "'1"), None);
+        assert!(cp.command_buffer.is_empty());
+        assert_eq!(
+            cp.parse_normal_mode_command(Character::Ctrl('o')),
+            Some(NormalModeCommand::JumpBack)
+        );
+        assert_eq!(
+            cp.parse_normal_mode_command(Character::Tab),
+            Some(NormalModeCommand::JumpForward)
+        );
+    }
+
+    #[test]
+    fn parse_command_replace() {
+        let mut cp = CommandParser::new();
+        assert_eq!(
+            parse_keys(&mut cp, "3rx"),
+            Some(NormalModeCommand::ReplaceChar('x'))
+        );
+        assert_eq!(cp.nr_prefix(), Some(3));
+        cp.clear_nr_prefix();
+        //The character isn't read as a command
+        assert_eq!(
+            parse_keys(&mut cp, "rj"),
+            Some(NormalModeCommand::ReplaceChar('j'))
+        );
+        assert_eq!(cp.parse_normal_mode_command(Character::Display('r')), None);
+        assert_eq!(cp.parse_normal_mode_command(Character::Esc), None);
+        assert!(cp.command_buffer.is_empty());
+        assert_eq!(
+            parse_keys(&mut cp, "R"),
+            Some(NormalModeCommand::EnterReplaceMode)
+        );
+        assert_eq!(
+            parse_keys(&mut cp, "~"),
+            Some(NormalModeCommand::ToggleCase)
+        );
+        assert_eq!(
+            cp.parse_visual_mode_command(Character::Display('~')),
+            Some(VisualModeCommand::Operate(Operator::ToggleCase))
+        );
+    }
+
+    #[test]
+    fn parse_visual_mode_command() {
+        let mut cp = CommandParser::new();
+        assert_eq!(
+            cp.parse_visual_mode_command(Character::Display('d')),
+            Some(VisualModeCommand::Operate(Operator::Delete))
+        );
+        assert_eq!(cp.parse_visual_mode_command(Character::Display('3')), None);
+        assert_eq!(
+            cp.parse_visual_mode_command(Character::Display('j')),
+            Some(VisualModeCommand::Normal(NormalModeCommand::MoveDown))
+        );
+        assert_eq!(cp.nr_prefix(), Some(3));
+        cp.clear_nr_prefix();
+        //After a prefix the key belongs to it
+        assert_eq!(cp.parse_visual_mode_command(Character::Display('g')), None);
+        assert_eq!(
+            cp.parse_visual_mode_command(Character::Display('d')),
+            Some(VisualModeCommand::Normal(NormalModeCommand::SetDueDate))
+        );
+        assert_eq!(
+            cp.parse_visual_mode_command(Character::Display('t')),
+            Some(VisualModeCommand::Normal(NormalModeCommand::ToggleDone))
+        );
+        assert_eq!(
+            cp.parse_visual_mode_command(Character::Esc),
+            Some(VisualModeCommand::Exit)
+        );
+    }
+
+    #[test]
+    fn parse_command_register() {
+        let mut cp = CommandParser::new();
+        assert_eq!(cp.parse_normal_mode_command(Character::Display('"')), None);
+        assert_eq!(cp.parse_normal_mode_command(Character::Display('A')), None);
+        assert_eq!(cp.register(), Some('A'));
+        assert_eq!(cp.parse_normal_mode_command(Character::Display('y')), None);
+        assert_eq!(
+            cp.parse_normal_mode_command(Character::Display('y')),
+            Some(NormalModeCommand::Operate(Operation {
+                operator: Operator::Yank,
+                motion: Motion::Line,
+                count: None
+            }))
+        );
+        assert!(cp.command_buffer.is_empty());
+
+        cp.clear_register();
+        assert_eq!(cp.parse_normal_mode_command(Character::Display('"')), None);
+        assert_eq!(cp.parse_normal_mode_command(Character::Display('%')), None);
+        assert_eq!(cp.register(), None);
+        assert_eq!(
+            cp.parse_normal_mode_command(Character::Display('p')),
+            Some(NormalModeCommand::Put)
+        );
+    }
 }
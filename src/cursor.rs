@@ -1,4 +1,4 @@
-use crate::min_todo::InputMode;
+use crate::{backend::CursorShape, min_todo::InputMode};
 
 //Represent current cursor position (0,0 based)
 #[derive(Debug, Default)]
@@ -8,37 +8,27 @@ pub struct Cursor {
 }
 
 impl Cursor {
-    pub fn render(&self, mode: &InputMode) {
-        print!(
-            "{}{}{}",
-            termion::cursor::Show,
-            termion::cursor::Goto(self.x as u16 + 1, self.y as u16 + 1), //We have to add 1, bcs
-            //GoTo() is 1,1 based, but
-            //we represent the coords
-            //as 0,0 based
-            match mode {
-                InputMode::Normal => termion::cursor::SteadyBlock.to_string(),
-                InputMode::Insert => termion::cursor::SteadyBar.to_string(),
-            },
-        );
+    pub fn shape(mode: &InputMode) -> CursorShape {
+        match mode {
+            InputMode::Normal | InputMode::Visual | InputMode::VisualLine => CursorShape::Block,
+            InputMode::Insert | InputMode::Command | InputMode::Search => CursorShape::Bar,
+            InputMode::Replace => CursorShape::Underline,
+        }
     }
 
+    //Stops at 0 when moving back further than that
     pub fn move_x(&mut self, x: i32) {
         if x.is_negative() {
-            if self.x > 0 {
-                self.x -= x.wrapping_abs() as usize;
-            }
+            self.x = self.x.saturating_sub(x.unsigned_abs() as usize);
         } else {
-            self.x += x.wrapping_abs() as usize;
+            self.x += x.unsigned_abs() as usize;
         }
     }
     pub fn move_y(&mut self, y: i32) {
         if y.is_negative() {
-            if self.y > 0 {
-                self.y -= y.wrapping_abs() as usize;
-            }
+            self.y = self.y.saturating_sub(y.unsigned_abs() as usize);
         } else {
-            self.y += y.wrapping_abs() as usize;
+            self.y += y.unsigned_abs() as usize;
         }
     }
 }
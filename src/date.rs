@@ -0,0 +1,159 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+//Calendar date in the ISO 8601 (YYYY-MM-DD) form used by todo files
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Date {
+    year: i32,
+    month: u32,
+    day: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseDateError;
+
+impl fmt::Display for ParseDateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid date, expected YYYY-MM-DD")
+    }
+}
+
+impl std::error::Error for ParseDateError {}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+impl Date {
+    //The years that fit in YYYY
+    pub const MIN: Self = Self {
+        year: 0,
+        month: 1,
+        day: 1,
+    };
+    pub const MAX: Self = Self {
+        year: 9999,
+        month: 12,
+        day: 31,
+    };
+
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        if (0..=9999).contains(&year) && day >= 1 && day <= days_in_month(year, month) {
+            Some(Self { year, month, day })
+        } else {
+            None
+        }
+    }
+
+    //There is no timezone database here, so "today" is the current UTC day
+    pub fn today() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self::from_days((secs / 86400) as i64)
+    }
+
+    //Days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    fn from_days(days: i64) -> Self {
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+        Self { year, month, day }
+    }
+
+    fn to_days(self) -> i64 {
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = (i64::from(self.month) + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    //None when it goes past MIN or MAX
+    pub fn add_days(self, days: i64) -> Option<Self> {
+        let days = self.to_days().checked_add(days)?;
+        (Self::MIN.to_days()..=Self::MAX.to_days())
+            .contains(&days)
+            .then(|| Self::from_days(days))
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = ParseDateError;
+
+    //Only the exact YYYY-MM-DD form is accepted, so a parsed date always prints back the same
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 10
+            || bytes[4] != b'-'
+            || bytes[7] != b'-'
+            || !bytes
+                .iter()
+                .enumerate()
+                .all(|(i, b)| i == 4 || i == 7 || b.is_ascii_digit())
+        {
+            return Err(ParseDateError);
+        }
+        let year = s[..4].parse().map_err(|_| ParseDateError)?;
+        let month = s[5..7].parse().map_err(|_| ParseDateError)?;
+        let day = s[8..].parse().map_err(|_| ParseDateError)?;
+        Self::new(year, month, day).ok_or(ParseDateError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Date;
+
+    #[test]
+    fn parse() {
+        assert_eq!("2024-02-29".parse(), Ok(Date::new(2024, 2, 29).unwrap()));
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("2023-13-01".parse::<Date>().is_err());
+        assert!("2023-1-01".parse::<Date>().is_err());
+        assert!("+023-01-01".parse::<Date>().is_err());
+        assert_eq!(Date::new(7, 1, 2).unwrap().to_string(), "0007-01-02");
+    }
+
+    #[test]
+    fn days() {
+        let date = Date::new(2023, 12, 31).unwrap();
+        assert_eq!(date.add_days(1), Date::new(2024, 1, 1));
+        assert_eq!(date.add_days(60), Date::new(2024, 2, 29));
+        assert_eq!(date.add_days(-365), Date::new(2022, 12, 31));
+        assert_eq!(Date::MAX.add_days(0), Some(Date::MAX));
+        assert_eq!(Date::MAX.add_days(1), None);
+        assert_eq!(Date::MIN.add_days(-1), None);
+        assert_eq!(date.add_days(i64::MAX), None);
+        assert_eq!(date.add_days(i64::MIN), None);
+        assert_eq!(Date::from_days(0), Date::new(1970, 1, 1).unwrap());
+    }
+}
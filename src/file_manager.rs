@@ -1,21 +1,374 @@
-pub struct FileManager{
-    
+use crate::{
+    format::Format,
+    todo_item::TodoItem,
+    todo_txt::{ParseMode, TodoTxtError},
+};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt, fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+//The line and column of each mark, by name
+pub type Marks = BTreeMap<char, (usize, usize)>;
+
+#[derive(Debug)]
+pub struct FileManager {
+    path: PathBuf,
+    status: FileManagerStatus,
+    line_ending: &'static str,
+    parse_mode: ParseMode,
+    format: Format,
 }
 
-pub enum FileManagerError{
+#[derive(Debug)]
+pub enum FileManagerError {
+    Io(PathBuf, io::Error),
+    PermissionDenied(PathBuf),
+    IsADirectory(PathBuf),
+    ReadOnly(PathBuf),
+    Parse(PathBuf, TodoTxtError),
+}
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FileManagerStatus {
+    Clean,
+    NewFile,
+    ReadOnly,
 }
 
-pub enum FileManagerStatus{
+impl fmt::Display for FileManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "\"{}\": {e}", path.display()),
+            Self::PermissionDenied(path) => write!(f, "\"{}\": Permission denied", path.display()),
+            Self::IsADirectory(path) => write!(f, "\"{}\" is a directory", path.display()),
+            Self::ReadOnly(path) => write!(f, "\"{}\" is read-only", path.display()),
+            Self::Parse(path, e) => write!(f, "\"{}\" {e}", path.display()),
+        }
+    }
+}
 
+impl Error for FileManagerError {}
+
+impl FileManagerError {
+    fn from_io(path: &Path, e: io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::PermissionDenied => Self::PermissionDenied(path.to_path_buf()),
+            _ => Self::Io(path.to_path_buf(), e),
+        }
+    }
 }
-impl FileManager{
-    //Setup and 
-    pub fn new() -> Result<Self,FileManagerError>{
-        let file = 
+
+impl FileManager {
+    //Setup and check what we can do with the file, nothing is read yet
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, FileManagerError> {
+        let path = path.as_ref().to_path_buf();
+
+        let status = match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => {
+                return Err(FileManagerError::IsADirectory(path));
+            }
+            //Opening for append doesn't touch the content, it only tells us if we can write
+            Ok(_) => match fs::OpenOptions::new().append(true).open(&path) {
+                Ok(_) => FileManagerStatus::Clean,
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => FileManagerStatus::ReadOnly,
+                Err(e) => return Err(FileManagerError::from_io(&path, e)),
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => FileManagerStatus::NewFile,
+            Err(e) => return Err(FileManagerError::from_io(&path, e)),
+        };
+
+        Ok(Self {
+            status,
+            line_ending: "\n",
+            parse_mode: ParseMode::default(),
+            format: Format::from_path(&path),
+            path,
+        })
+    }
+
+    pub fn parse_mode(&self) -> ParseMode {
+        self.parse_mode
+    }
+
+    pub fn set_parse_mode(&mut self, parse_mode: ParseMode) {
+        self.parse_mode = parse_mode;
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    //Overrides the format picked from the file extension
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    pub fn load(&mut self) -> Result<Vec<TodoItem>, FileManagerError> {
+        if self.status == FileManagerStatus::NewFile {
+            return Ok(Vec::new());
+        }
+
+        let content =
+            fs::read_to_string(&self.path).map_err(|e| FileManagerError::from_io(&self.path, e))?;
+
+        //Keep the line endings the file already uses
+        if content.contains("\r\n") {
+            self.line_ending = "\r\n";
+        }
+        let items = self
+            .format
+            .parse(&content, self.parse_mode)
+            .map_err(|e| FileManagerError::Parse(self.path.clone(), e))?;
+
+        Ok(items)
+    }
+
+    //Forced like :w! it tries anyway, the permissions may have changed since the file was opened
+    pub fn save(&mut self, items: &[TodoItem], force: bool) -> Result<(), FileManagerError> {
+        if !force && self.status == FileManagerStatus::ReadOnly {
+            return Err(FileManagerError::ReadOnly(self.path.clone()));
+        }
+
+        let mut content = String::new();
+        for item in items {
+            content.push_str(&self.format.serialize(item));
+            content.push_str(self.line_ending);
+        }
+
+        fs::write(&self.path, content).map_err(|e| FileManagerError::from_io(&self.path, e))?;
+
+        self.status = FileManagerStatus::Clean;
+        Ok(())
+    }
+
+    //Done items go to done.txt (or done.md, ...) next to the file, like todo.sh archive does
+    pub fn archive_path(&self) -> PathBuf {
+        let extension = self
+            .path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("txt");
+        self.path.with_file_name(format!("done.{extension}"))
+    }
+
+    pub fn archive(&self, items: &[TodoItem]) -> Result<PathBuf, FileManagerError> {
+        let path = self.archive_path();
+        let mut content = String::new();
+        for item in items {
+            content.push_str(&self.format.serialize(item));
+            content.push_str(self.line_ending);
+        }
+
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| FileManagerError::from_io(&path, e))?;
+        Ok(path)
+    }
+
+    //Marks are kept in .todo.txt.marks next to the file, one per line as the name, the line
+    //number and the column, e.g. "a 12 4"
+    pub fn marks_path(&self) -> PathBuf {
+        let name = self
+            .path
+            .file_name()
+            .map_or_else(Default::default, |name| name.to_string_lossy());
+        self.path.with_file_name(format!(".{name}.marks"))
     }
 
-    pub fn status(&self) -> FileManagerStatus{
+    //Marks that can't be read are just not there
+    pub fn load_marks(&self) -> Marks {
+        let Ok(content) = fs::read_to_string(self.marks_path()) else {
+            return Marks::new();
+        };
+        content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                let name = fields.next()?.parse().ok()?;
+                let nr: usize = fields.next()?.parse().ok()?;
+                let x = fields.next()?.parse().ok()?;
+                Some((name, (nr.checked_sub(1)?, x)))
+            })
+            .collect()
+    }
+
+    //Without marks the file is removed, so there's none lying around for nothing
+    pub fn save_marks(&self, marks: &Marks) -> Result<(), FileManagerError> {
+        let path = self.marks_path();
+        let result = if marks.is_empty() {
+            fs::remove_file(&path).or_else(|e| match e.kind() {
+                ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            })
+        } else {
+            let content: String = marks
+                .iter()
+                .map(|(name, (line, x))| format!("{name} {} {x}\n", line + 1))
+                .collect();
+            fs::write(&path, content)
+        };
+        result.map_err(|e| FileManagerError::from_io(&path, e))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn status(&self) -> FileManagerStatus {
         self.status
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{FileManager, FileManagerError, FileManagerStatus, Marks};
+    use crate::{
+        format::Format,
+        todo_item::TodoItem,
+        todo_txt::{ParseMode, TodoTxtError},
+    };
+    use std::{fs, path::PathBuf};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("min_todo_{}_{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn new_file() {
+        let path = temp_path("new_file");
+        let mut fm = FileManager::new(&path).unwrap();
+        assert_eq!(fm.status(), FileManagerStatus::NewFile);
+        assert!(fm.load().unwrap().is_empty());
+
+        fm.save(&[TodoItem::from("first"), TodoItem::from("second")], false)
+            .unwrap();
+        assert_eq!(fm.status(), FileManagerStatus::Clean);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_and_save() {
+        let path = temp_path("load_and_save");
+        fs::write(&path, "a\r\nb\r\n").unwrap();
+
+        let mut fm = FileManager::new(&path).unwrap();
+        assert_eq!(fm.status(), FileManagerStatus::Clean);
+        let lines = fm.load().unwrap();
+        assert_eq!(
+            lines.iter().map(|l| l.to_string()).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+
+        fm.save(&lines, false).unwrap();
+        assert_eq!(fm.status(), FileManagerStatus::Clean);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\r\nb\r\n");
+
+        //Read-only files are only written when forced
+        fm.status = FileManagerStatus::ReadOnly;
+        assert!(matches!(
+            fm.save(&lines[..1], false),
+            Err(FileManagerError::ReadOnly(_))
+        ));
+        fm.save(&lines[..1], true).unwrap();
+        assert_eq!(fm.status(), FileManagerStatus::Clean);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\r\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn strict_parse_error() {
+        let path = temp_path("strict_parse_error");
+        fs::write(&path, "(A) fine\nX not fine\n").unwrap();
+
+        let mut fm = FileManager::new(&path).unwrap();
+        assert_eq!(fm.load().unwrap().len(), 2);
+        fm.set_parse_mode(ParseMode::Strict);
+        assert!(matches!(
+            fm.load(),
+            Err(FileManagerError::Parse(_, TodoTxtError { line: 2, .. }))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn markdown() {
+        let path = temp_path("markdown.md");
+        fs::write(&path, "# Todo\n- [ ] open\n  - [x] done\n").unwrap();
+
+        let mut fm = FileManager::new(&path).unwrap();
+        assert_eq!(fm.format(), Format::Markdown);
+        let items = fm.load().unwrap();
+        assert!(items[2].done);
+        fm.save(&items, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Todo\n- [ ] open\n  - [x] done\n"
+        );
+
+        fm.set_format(Format::TodoTxt);
+        let items = fm.load().unwrap();
+        assert_eq!(items[2].indent, "  ");
+        assert_eq!(items[2].description, "- [x] done");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn archive() {
+        let path = temp_path("archive_dir");
+        fs::create_dir_all(&path).unwrap();
+        let fm = FileManager::new(path.join("todo.txt")).unwrap();
+        assert_eq!(fm.archive_path(), path.join("done.txt"));
+
+        fm.archive(&[TodoItem::from("x first")]).unwrap();
+        fm.archive(&[TodoItem::from("x second")]).unwrap();
+        assert_eq!(
+            fs::read_to_string(fm.archive_path()).unwrap(),
+            "x first\nx second\n"
+        );
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn marks() {
+        let path = temp_path("marks_dir");
+        fs::create_dir_all(&path).unwrap();
+        let fm = FileManager::new(path.join("todo.txt")).unwrap();
+        assert_eq!(fm.marks_path(), path.join(".todo.txt.marks"));
+        assert!(fm.load_marks().is_empty());
+
+        let marks = Marks::from([('a', (0, 3)), ('q', (11, 0))]);
+        fm.save_marks(&marks).unwrap();
+        assert_eq!(
+            fs::read_to_string(fm.marks_path()).unwrap(),
+            "a 1 3\nq 12 0\n"
+        );
+        assert_eq!(fm.load_marks(), marks);
+
+        //Broken lines are skipped
+        fs::write(fm.marks_path(), "a 1 3\nb x 2\nc 0 1\n\n").unwrap();
+        assert_eq!(fm.load_marks(), Marks::from([('a', (0, 3))]));
+
+        fm.save_marks(&Marks::new()).unwrap();
+        assert!(!fm.marks_path().exists());
+        fm.save_marks(&Marks::new()).unwrap();
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn directory() {
+        assert!(matches!(
+            FileManager::new(std::env::temp_dir()),
+            Err(FileManagerError::IsADirectory(_))
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    markdown,
+    todo_item::TodoItem,
+    todo_txt::{self, ParseMode, TodoTxtError},
+};
+use std::path::Path;
+
+//How items are written in the file
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Format {
+    #[default]
+    TodoTxt,
+    Markdown,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("md" | "markdown") => Self::Markdown,
+            _ => Self::TodoTxt,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "todotxt" | "todo.txt" | "txt" => Some(Self::TodoTxt),
+            "markdown" | "md" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+
+    //The parse mode only matters for todo.txt, markdown keeps anything it doesn't know as notes
+    pub fn parse(self, content: &str, mode: ParseMode) -> Result<Vec<TodoItem>, TodoTxtError> {
+        match self {
+            Self::TodoTxt => todo_txt::parse(content, mode),
+            Self::Markdown => Ok(markdown::parse(content)),
+        }
+    }
+
+    pub fn parse_line(self, line: &str) -> TodoItem {
+        match self {
+            Self::TodoTxt => todo_txt::parse_item(line),
+            Self::Markdown => markdown::parse_item(line),
+        }
+    }
+
+    pub fn serialize(self, item: &TodoItem) -> String {
+        match self {
+            Self::TodoTxt => todo_txt::serialize(item),
+            Self::Markdown => markdown::serialize(item),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Format;
+    use std::path::Path;
+
+    #[test]
+    fn from_path() {
+        assert_eq!(Format::from_path(Path::new("todo.txt")), Format::TodoTxt);
+        assert_eq!(Format::from_path(Path::new("README.md")), Format::Markdown);
+        assert_eq!(
+            Format::from_path(Path::new("notes.markdown")),
+            Format::Markdown
+        );
+        assert_eq!(Format::from_path(Path::new("todo")), Format::TodoTxt);
+    }
+
+    #[test]
+    fn convert() {
+        let item = Format::Markdown.parse_line("  - [x] 2024-01-02 done +project");
+        assert_eq!(
+            Format::TodoTxt.serialize(&item),
+            "  x 2024-01-02 done +project"
+        );
+        let item = Format::TodoTxt.parse_line("(A) open");
+        assert_eq!(Format::Markdown.serialize(&item), "- [ ] (A) open");
+    }
+}
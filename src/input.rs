@@ -0,0 +1,119 @@
+use crate::char_parser::{Decoder, Event};
+use signal_hook::{consts::SIGWINCH, iterator::Signals};
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+//How long to wait for the rest of an escape sequence before taking ESC as the Esc key.
+//Terminals send a whole sequence at once, so this only has to cover slow connections.
+const ESC_TIMEOUT: Duration = Duration::from_millis(50);
+//How long a paste can go without any of its text before its end marker is taken as lost.
+//Pastes over slow connections arrive in pieces, this has to be far more than ESC_TIMEOUT.
+const PASTE_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Message {
+    Bytes(Vec<u8>),
+    Resize,
+    Closed, //stdin is at its end, the resize thread keeps the channel open
+}
+
+//Reads stdin on its own thread, so we can stop waiting for more bytes after a lone ESC.
+//Resizes are waited for on another one, they arrive in between the keys.
+pub struct Input {
+    receiver: Receiver<Message>,
+    decoder: Decoder,
+    events: VecDeque<Event>,
+    closed: bool,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self::listen_for_resize(sender.clone());
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut buffer = [0; 1024];
+            loop {
+                match stdin.read(&mut buffer) {
+                    Ok(0) | Err(_) => {
+                        let _ = sender.send(Message::Closed);
+                        break;
+                    }
+                    Ok(n) => {
+                        if sender.send(Message::Bytes(buffer[..n].to_vec())).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Self {
+            receiver,
+            decoder: Decoder::new(),
+            events: VecDeque::new(),
+            closed: false,
+        }
+    }
+
+    fn listen_for_resize(sender: Sender<Message>) {
+        //Without the signal the size just stays as it was at the start
+        let Ok(mut signals) = Signals::new([SIGWINCH]) else {
+            return;
+        };
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                if sender.send(Message::Resize).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    //Blocks until the next key, paste or resize, None once stdin is closed
+    pub fn next_event(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(event);
+            }
+            if let Some(event) = self.decoder.next_event() {
+                return Some(event);
+            }
+            if self.closed {
+                return None;
+            }
+            let message = if self.decoder.is_pending() {
+                let pasting = self.decoder.is_pasting();
+                let timeout = if pasting { PASTE_TIMEOUT } else { ESC_TIMEOUT };
+                match self.receiver.recv_timeout(timeout) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        if pasting {
+                            self.events.extend(self.decoder.end_paste());
+                        } else {
+                            self.events.extend(self.decoder.flush());
+                        }
+                        if e == RecvTimeoutError::Disconnected && self.events.is_empty() {
+                            return None;
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                self.receiver.recv().ok()?
+            };
+            match message {
+                Message::Bytes(bytes) => self.decoder.feed(&bytes),
+                Message::Resize => self.events.push_back(Event::Resize),
+                Message::Closed => {
+                    self.events.extend(self.decoder.flush());
+                    self.events.extend(self.decoder.end_paste());
+                    self.closed = true;
+                }
+            }
+        }
+    }
+}
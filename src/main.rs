@@ -1,29 +1,99 @@
-// mod file_manager;
+mod backend;
 mod char_parser;
+mod command_line;
 mod command_parser;
 mod cursor;
+mod date;
+mod file_manager;
+mod format;
+mod input;
+mod markdown;
 mod min_todo;
+mod query;
+mod registers;
+mod screen;
+mod search;
+mod sort;
+mod text;
+mod todo_item;
+mod todo_txt;
+mod tree;
+mod undo;
 
 use std::io::Write;
 
-use min_todo::{Line, MinTodo};
+use backend::TermionBackend;
+use file_manager::FileManager;
+use format::Format;
+use input::Input;
+use min_todo::MinTodo;
 use termion::raw::IntoRawMode;
+use todo_item::TodoItem;
+use todo_txt::ParseMode;
+
+const USAGE: &str = "usage: min_todo [--strict] [--format todotxt|markdown] [file]";
 
 fn main() {
-    print!("{}", termion::screen::ToAlternateScreen);
+    let mut path = None;
+    let mut parse_mode = ParseMode::Lenient;
+    let mut format = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strict" => parse_mode = ParseMode::Strict,
+            "--format" => match args.next().as_deref().and_then(Format::from_name) {
+                Some(f) => format = Some(f),
+                None => {
+                    eprintln!("{USAGE}");
+                    std::process::exit(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if arg.starts_with('-') || path.is_some() => {
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    //Open the file before switching screens, so errors end up in the normal terminal
+    let mut renderer = match path {
+        Some(path) => match FileManager::new(path).and_then(|mut file_manager| {
+            file_manager.set_parse_mode(parse_mode);
+            if let Some(format) = format {
+                file_manager.set_format(format);
+            }
+            MinTodo::open(file_manager, Box::new(TermionBackend::new()))
+        }) {
+            Ok(renderer) => renderer,
+            Err(e) => {
+                eprintln!("min_todo: {e}");
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let mut renderer = MinTodo::new(Box::new(TermionBackend::new()));
+            renderer.add_line(TodoItem::default());
+            renderer
+        }
+    };
+
+    //Bracketed paste, so pasted text can't run as commands
+    print!("{}\x1b[?2004h", termion::screen::ToAlternateScreen);
     let stdout = std::io::stdout().into_raw_mode().unwrap();
     stdout.lock().flush().unwrap();
-    let mut renderer = MinTodo::new();
-    for i in 0..153 {
-        renderer.add_line(Line::from(i.to_string() + " Test Line "));
-    }
 
+    let mut input = Input::new();
     loop {
         renderer.render().unwrap();
-        if !renderer.update().unwrap() {
+        if !renderer.update(&mut input).unwrap() {
             break;
         }
     }
-    print!("{}", termion::screen::ToMainScreen);
+    print!("\x1b[?2004l{}", termion::screen::ToMainScreen);
     stdout.lock().flush().unwrap();
 }
@@ -0,0 +1,94 @@
+//Markdown task lists, e.g. "- [ ] task" or "  * [x] nested task".
+//Headings, prose and other list items are kept as notes.
+use crate::{
+    todo_item::{ItemKind, TodoItem},
+    todo_txt,
+};
+
+pub fn parse_item(line: &str) -> TodoItem {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let indent = &line[..line.len() - rest.len()];
+
+    //"- [ ] " is 6 bytes, the text after it is read like a todo.txt line
+    match (rest.get(..1), rest.get(1..6)) {
+        (Some(bullet @ ("-" | "*" | "+")), Some(checkbox @ (" [ ] " | " [x] " | " [X] "))) => {
+            let mut item = todo_txt::parse_fields(checkbox != " [ ] ", &rest[6..]);
+            item.indent = indent.to_string();
+            item.bullet = bullet.chars().next();
+            item
+        }
+        _ => TodoItem {
+            kind: ItemKind::Note,
+            description: line.to_string(),
+            ..TodoItem::default()
+        },
+    }
+}
+
+pub fn parse(content: &str) -> Vec<TodoItem> {
+    content.lines().map(parse_item).collect()
+}
+
+//[X] is written back as [x]
+pub fn serialize(item: &TodoItem) -> String {
+    match item.kind {
+        ItemKind::Note => item.description.clone(),
+        ItemKind::Task => format!(
+            "{}{} [{}] {}",
+            item.indent,
+            item.bullet.unwrap_or('-'),
+            if item.done { 'x' } else { ' ' },
+            todo_txt::serialize_fields(item)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, parse_item, serialize};
+    use crate::{date::Date, todo_item::ItemKind};
+
+    #[test]
+    fn parse_items() {
+        let items =
+            parse("# Groceries\n\n- [ ] milk\n  * [x] 2024-01-02 eggs\n+ [ ] (A) bread @shop\n");
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[0].kind, ItemKind::Note);
+        assert_eq!(items[1].kind, ItemKind::Note);
+
+        assert_eq!(items[2].kind, ItemKind::Task);
+        assert!(!items[2].done);
+        assert_eq!(items[2].description, "milk");
+
+        assert!(items[3].done);
+        assert_eq!(items[3].indent, "  ");
+        assert_eq!(items[3].bullet, Some('*'));
+        assert_eq!(items[3].completion_date, Date::new(2024, 1, 2));
+        assert_eq!(items[3].description, "eggs");
+
+        assert_eq!(items[4].priority, Some('A'));
+        assert_eq!(items[4].description, "bread @shop");
+    }
+
+    #[test]
+    fn round_trip() {
+        for line in [
+            "",
+            "# Heading",
+            "Some prose with a [ ] in it",
+            "- plain list item",
+            "- [ ]",
+            "- [ ] ",
+            "- [ ] task",
+            "-  [ ] two spaces",
+            "- [] no space",
+            "\t* [x] tab indented",
+            "    + [ ] (B) 2024-01-01 deep +project",
+            "1. [ ] numbered lists aren't tasks",
+            "–[ ] not a bullet",
+        ] {
+            assert_eq!(serialize(&parse_item(line)), line);
+        }
+        assert_eq!(serialize(&parse_item("- [X] done")), "- [x] done");
+    }
+}
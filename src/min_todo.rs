@@ -1,481 +1,2474 @@
-pub type Buffer = [u8; 4];
-
 use crate::{
-    char_parser,
-    command_parser::{CommandParser, InsertModeCommand, NormalModeCommand},
+    backend::Backend,
+    char_parser::{Character, Event},
+    command_line::{CommandLine, ExCommand, ExCommandError, Setting},
+    command_parser::{
+        CommandModeCommand, CommandParser, InsertModeCommand, Motion, NormalModeCommand, Operation,
+        Operator, VisualModeCommand,
+    },
     cursor::Cursor,
+    date::Date,
+    file_manager::{FileManager, FileManagerError, FileManagerStatus, Marks},
+    format::Format,
+    input::Input,
+    query::Query,
+    registers::{Register, Registers},
+    screen::{restyle_spans, slice_spans, Color, Grid, Span, Style},
+    search,
+    sort::{self, SortKey},
+    text,
+    todo_item::{ItemKind, TodoItem},
+    tree::{self, SHIFT_WIDTH},
+    undo::{LineShift, UndoTree, ViewState},
 };
-use std::{
-    error::Error,
-    io::{self, Read, Write},
-};
+use regex::Regex;
+use std::{cmp::Ordering, error::Error};
 
 #[derive(Debug)]
 pub struct Line {
-    pub content: String,
-    pub width: usize,
+    pub spans: Vec<Span>,
 }
 
 impl Line {
-    pub fn new() -> Self {
-        Self {
-            content: String::new(),
-            width: 0,
-        }
-    }
-
     pub fn from<T: std::fmt::Display>(content: T) -> Self {
-        Self {
-            content: content.to_string(),
-            width: content.to_string().len(),
-        }
-    }
-
-    pub fn len(&self) -> usize {
-        self.content.len()
+        Self::styled(vec![Span::plain(content.to_string())])
     }
 
-    pub fn render(&self) {
-        // let width = self.width.min(self.len());
-        print!("\n\r{}", self.content);
-        // print!("\n\r{}", &self.content[..width]);
+    pub fn styled(spans: Vec<Span>) -> Self {
+        Self { spans }
     }
 }
 
-#[derive(Debug)]
+//Rows kept visible above and below the cursor when scrolling
+const SCROLL_MARGIN: usize = 5;
+
+//How deep macros can play each other, so one that plays itself ends
+const MAX_MACRO_DEPTH: usize = 100;
+//Like vim the jump list forgets the oldest jumps
+const MAX_JUMPS: usize = 100;
+
+#[derive(Debug, PartialEq)]
 pub enum InputMode {
     Normal,
     Insert,
+    Command,
+    Visual,
+    VisualLine,
+    Search,
+    Replace,
+}
+
+//A search pattern being typed, the cursor moves to the first match as it changes
+#[derive(Debug)]
+struct SearchPrompt {
+    forward: bool,
+    start: ViewState, //Where the search started, Esc goes back there
+    count: Option<usize>,
+    //An operator waiting for the pattern as its motion, with the register it was given
+    operation: Option<(Operation, Option<char>)>,
+}
+
+//The last change, for . to do again
+#[derive(Debug, Clone)]
+struct Change {
+    command: NormalModeCommand,
+    count: Option<usize>,
+    register: Option<char>,
+    typed: Vec<Character>, //The keys after it until normal mode, like the text inserted
+}
+
+//What R is overwriting, Backspace puts the old characters back
+#[derive(Debug)]
+struct ReplaceState {
+    original: String, //The line before it was overwritten
+    start: usize,
+    typed: String,
+    count: usize, //What was typed is repeated this many times in all
 }
 
 #[derive(Debug)]
 pub struct MinTodo {
-    content: Vec<Line>,
+    content: Vec<TodoItem>,
     cursor: Cursor,
     changed: bool,
     scroll_beg: usize,
     scroll_end: usize,
     start_scroll_up: usize,
     start_scroll_down: usize,
-    term_columns: usize,
+    view_rows: usize, //Rows for the content, the terminal's height without the bottom line
     command_parser: CommandParser,
     mode: InputMode,
     bottom_line: Option<Line>,
+    file_manager: Option<FileManager>,
+    modified: bool, //Changed since the last write, also without a file to write to
+    message: Option<String>,
+    command_line: CommandLine,
+    ex_count: Option<usize>, //Count typed before ':', used by the commands working on items
+    backend: Box<dyn Backend>,
+    frame: Option<Grid>, //What was drawn last, the next frame only draws what changed
+    wrap: bool,          //Soft wrap long lines, otherwise they scroll sideways by scroll_x
+    scroll_x: usize,     //The first column shown
+    undo_tree: UndoTree,
+    undo_start: Option<ViewState>, //View at the start of the change group being built
+    archived: Vec<TodoItem>,       //Archived items, they go to done.txt with the next write
+    line_shifts: Vec<LineShift>,   //How the lines moved in the change group, for the marks
+    registers: Registers,
+    visual_start: (usize, usize), //Line and column where the selection was started
+    search_line: CommandLine,
+    search_prompt: Option<SearchPrompt>,
+    replace: Option<ReplaceState>,
+    last_change: Option<Change>,
+    recording_change: bool, //The keys typed are part of the last change
+    recording: Option<(char, Vec<Character>)>, //The macro register and the keys so far
+    last_macro: Option<char>,
+    playing: usize, //How many macros are playing, the changes are undone together
+    search: Option<Regex>, //The last search, for n and N
+    search_forward: bool,
+    highlight: bool, //Show the matches of the last search, until :noh
+    filter: Option<Query>,
+    //The content index of each row, only the lines matching the filter and not in a folded
+    //subtree. The cursor and the scroll positions are rows, edits go to the line the row shows.
+    view: Vec<usize>,
+    folds: Vec<usize>, //Content indices of the folded parents, in order
+    //Positions are content indices and columns, they move with the lines around them
+    marks: Marks,
+    jumps: Vec<(usize, usize)>, //Where G, gg, searches and marks jumped from, oldest first
+    jump_idx: usize,            //The entry Ctrl-O went back to, jumps.len() before that
 }
 
 impl MinTodo {
-    pub fn new() -> Self {
-        let term_columns = termion::terminal_size().unwrap().1 as usize;
-        Self {
+    pub fn new(backend: Box<dyn Backend>) -> Self {
+        //Without a tty fall back to the classic 80x24
+        let rows = backend.size().map(|s| s.1).unwrap_or(24) as usize;
+        let mut min_todo = Self {
             content: Vec::new(),
             cursor: Cursor::default(),
             changed: true,
             scroll_beg: 0,
-            scroll_end: term_columns,
-            term_columns,
-            start_scroll_up: 5,
-            start_scroll_down: term_columns - 5,
+            scroll_end: 0,
+            view_rows: 0,
+            start_scroll_up: 0,
+            start_scroll_down: 0,
             command_parser: CommandParser::new(),
             mode: InputMode::Normal,
             bottom_line: None,
-        }
+            file_manager: None,
+            modified: false,
+            message: None,
+            command_line: CommandLine::new(),
+            ex_count: None,
+            backend,
+            frame: None,
+            wrap: false,
+            scroll_x: 0,
+            undo_tree: UndoTree::new(&[]),
+            undo_start: None,
+            archived: Vec::new(),
+            line_shifts: Vec::new(),
+            registers: Registers::new(),
+            visual_start: (0, 0),
+            search_line: CommandLine::new(),
+            search_prompt: None,
+            replace: None,
+            last_change: None,
+            recording_change: false,
+            recording: None,
+            last_macro: None,
+            playing: 0,
+            search: None,
+            search_forward: true,
+            highlight: false,
+            filter: None,
+            view: Vec::new(),
+            folds: Vec::new(),
+            marks: Marks::new(),
+            jumps: Vec::new(),
+            jump_idx: 0,
+        };
+        min_todo.set_size(rows);
+        min_todo
     }
 
-    pub fn add_line(&mut self, line: Line) {
-        self.content.push(line);
-        // self.scroll_end += 1;
+    //The terminal changed its size
+    fn resize(&mut self) {
+        let rows = self.backend.size().map(|s| s.1).unwrap_or(24) as usize;
+        self.set_size(rows);
+        //The old frame is of no use anymore, everything is drawn again
+        self.frame = None;
+        self.changed = true;
+    }
+
+    fn set_size(&mut self, rows: usize) {
+        //The last row is reserved for the bottom line, but there's always one for the content
+        self.view_rows = rows.saturating_sub(1).max(1);
+        //The margins shrink on small terminals, so they never overlap
+        let margin = SCROLL_MARGIN.min((self.view_rows - 1) / 2);
+        self.start_scroll_up = margin;
+        self.start_scroll_down = (self.view_rows - margin).min(self.view_rows - 1);
+        self.fit_view();
     }
 
-    //Clear and move the cursor to (1,1)
-    fn clear() {
-        print!("{}{}", termion::cursor::Goto(1, 1), termion::clear::All);
+    //Scrolls so the current line is on screen, within the margins if there are lines to scroll
+    fn fit_view(&mut self) {
+        //The filter may show fewer rows than there were
+        let row = self.curr_row().min(self.view.len().saturating_sub(1));
+        self.scroll_beg = row.saturating_sub(self.cursor.y.min(self.start_scroll_down));
+        //Don't leave rows empty at the bottom when there are lines above to show
+        let last_page = self.view.len().saturating_sub(self.view_rows);
+        self.scroll_beg = self.scroll_beg.min(last_page);
+        self.scroll_end = self.scroll_beg + self.view_rows;
+        self.cursor.y = row - self.scroll_beg;
     }
 
-    fn flush() -> Result<(), Box<dyn Error>> {
-        std::io::stdout().flush()?;
+    pub fn open(
+        file_manager: FileManager,
+        backend: Box<dyn Backend>,
+    ) -> Result<Self, FileManagerError> {
+        let mut min_todo = Self::new(backend);
+        min_todo.load(file_manager)?;
+        Ok(min_todo)
+    }
 
+    fn load(&mut self, mut file_manager: FileManager) -> Result<(), FileManagerError> {
+        self.content = file_manager.load()?;
+        if self.content.is_empty() {
+            //There always has to be a line for the cursor to be on
+            self.content.push(TodoItem::default());
+        }
+        self.refresh_view();
+        self.archived.clear();
+        self.marks = file_manager.load_marks();
+        self.marks.retain(|_, (line, _)| *line < self.content.len());
+        self.jumps.clear();
+        self.jump_idx = 0;
+        self.file_manager = Some(file_manager);
+        self.move_to_top();
+        self.cursor.x = 0;
+        self.undo_tree = UndoTree::new(&self.content);
+        self.line_shifts.clear();
+        self.modified = false;
         Ok(())
     }
 
-    fn exit() -> bool {
-        Self::clear();
-        let _ = Self::flush(); //Here we don't care if we succed or not.
-        false
+    pub fn save(&mut self, force: bool) -> Result<(), Box<dyn Error>> {
+        let mut file_manager = self.file_manager.take().ok_or(ExCommandError::NoFileName)?;
+        let result = self.write_file(&mut file_manager, force);
+        self.file_manager = Some(file_manager);
+        result
     }
 
-    fn move_cur_down(&mut self) {
-        if self.cursor.y >= self.start_scroll_down && self.scroll_end < self.content.len() {
-            if self.scroll_beg < self.scroll_end {
-                self.scroll_beg += 1;
-            }
-            self.scroll_end += 1;
-        } else if self.curr_line_nr() < self.content.len() - 1 {
-            self.cursor.move_y(1);
+    //Writes the buffer to the file, which doesn't have to be the one it's attached to yet
+    fn write_file(
+        &mut self,
+        file_manager: &mut FileManager,
+        force: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        //Before the lines, so an error can't lose the items. Once written they stay there.
+        if !self.archived.is_empty() {
+            file_manager.archive(&self.archived)?;
+            self.archived.clear();
+            self.undo_tree.clear_archived();
         }
+        file_manager.save(&self.content, force)?;
+        self.undo_tree.mark_saved();
+        self.modified = false;
+        //The lines are written either way, a failure here is only about the marks
+        file_manager.save_marks(&self.marks)?;
+        self.message = Some(format!(
+            "\"{}\" {}L written",
+            file_manager.path().display(),
+            self.content.len()
+        ));
+        Ok(())
     }
-    fn move_cur_up(&mut self) {
-        if self.cursor.y == self.start_scroll_up && self.scroll_beg > 0
-            || (self.cursor.y == 0 && self.scroll_beg != 0)
-        {
-            self.scroll_beg -= 1;
-            if self.scroll_end > 0 {
-                self.scroll_end -= 1;
-            }
-        } else {
-            self.cursor.move_y(-1);
+
+    //Leaving the file, the marks are only kept when they fit the lines that were saved
+    fn keep_marks(&self) {
+        if let Some(file_manager) = self.file_manager.as_ref().filter(|_| !self.is_modified()) {
+            //Nothing to do about it on the way out
+            let _ = file_manager.save_marks(&self.marks);
         }
     }
 
-    //TODO: make this more like vim
-    fn move_to_line(&mut self, line: usize) {
-        if line >= self.content.len() - 1 {
-            self.move_to_bottom();
-            return;
-        }
+    fn is_modified(&self) -> bool {
+        self.modified
+    }
 
-        self.scroll_beg = line - 1;
-        self.scroll_end = self.scroll_beg + self.term_columns;
-        self.cursor.y = 0; //self.term_columns as i32 / 2;
+    fn format(&self) -> Format {
+        self.file_manager
+            .as_ref()
+            .map(|fm| fm.format())
+            .unwrap_or_default()
     }
 
-    fn move_to_top(&mut self) {
-        self.scroll_beg = 0;
-        self.scroll_end = self.term_columns;
-        self.cursor.y = 0;
+    fn set_modified(&mut self) {
+        self.modified = true;
     }
 
-    fn move_to_bottom(&mut self) {
-        self.scroll_end = self.content.len();
-        self.scroll_beg = self.scroll_end - self.term_columns;
-        self.cursor.y = self.term_columns - 1;
+    //Used to set up the initial content, so it can't be undone
+    pub fn add_line(&mut self, item: TodoItem) {
+        self.content.push(item);
+        self.refresh_view();
+        self.undo_tree = UndoTree::new(&self.content);
+        self.line_shifts.clear();
+        // self.scroll_end += 1;
     }
 
-    fn curr_line_nr(&self) -> usize {
-        self.cursor.y + self.scroll_beg
+    //Applies the filter and the folds again. Lines are only hidden here, so a line that was
+    //edited and no longer matches stays until the filter is set again.
+    fn refresh_view(&mut self) {
+        let today = Date::today();
+        self.folds.retain(|idx| *idx < self.content.len());
+        let levels = tree::levels(&self.content);
+        let mut folded = vec![false; self.content.len()];
+        for idx in &self.folds {
+            folded[idx + 1..tree::subtree_end(&levels, *idx)].fill(true);
+        }
+        self.view = (0..self.content.len())
+            .filter(|idx| {
+                !folded[*idx]
+                    && self
+                        .filter
+                        .as_ref()
+                        .is_none_or(|filter| filter.matches(&self.content[*idx], today))
+            })
+            .collect();
+        //There has to be a row for the cursor to be on
+        if self.view.is_empty() {
+            if let Some(filter) = self.filter.take() {
+                self.message = Some(format!("No items match the filter: {filter}"));
+                self.view = (0..self.content.len()).collect();
+            }
+        }
     }
 
-    fn curr_line_len(&self) -> usize {
-        self.content.get(self.curr_line_nr()).unwrap().len()
+    fn set_filter(&mut self, filter: Option<Query>) {
+        let line = self.curr_line_nr();
+        self.filter = filter;
+        self.refresh_view();
+        //Stay on the same line, or the next one that's shown
+        let row = self.view.partition_point(|idx| *idx < line);
+        self.scroll_beg = 0;
+        self.cursor.y = row.min(self.view.len() - 1);
+        self.fit_view();
     }
 
-    fn curr_line(&self) -> &Line {
-        self.content.get(self.curr_line_nr()).unwrap()
+    //Keeps the cursor on the line, or on the closest one above it that's shown, like the
+    //parent of a subtree that was just folded
+    fn show_line(&mut self, line: usize) {
+        let row = self.row_of(line);
+        self.scroll_beg = self.scroll_beg.min(row);
+        self.cursor.y = row - self.scroll_beg;
+        self.fit_view();
     }
 
-    fn curr_line_mut(&mut self) -> &mut Line {
-        let idx = self.curr_line_nr();
-        self.content.get_mut(idx).unwrap()
+    //The row showing the line, or the closest one above it when it's hidden
+    fn row_of(&self, line: usize) -> usize {
+        self.view
+            .partition_point(|idx| *idx <= line)
+            .saturating_sub(1)
     }
 
-    fn align_cursor(&mut self) {
-        //It's safe to unwrap here
-        let cll = self.curr_line_len().max(1) - 1;
-        if cll < self.cursor.x {
-            self.cursor.x = cll;
+    //Inserts items at a content index, they are shown right where they were put
+    fn insert_lines(&mut self, at: usize, items: Vec<TodoItem>) {
+        let n = items.len();
+        self.content.splice(at..at, items);
+        let pos = self.view.partition_point(|idx| *idx < at);
+        for idx in &mut self.view[pos..] {
+            *idx += n;
         }
+        self.view.splice(pos..pos, at..at + n);
+        for idx in &mut self.folds {
+            if *idx >= at {
+                *idx += n;
+            }
+        }
+        let shift = LineShift::Insert {
+            lines: (at..at + n).collect(),
+            marks: Vec::new(),
+        };
+        self.shift_positions(&shift);
+        self.line_shifts.push(shift);
     }
 
-    fn handle_normal_mode_command(&mut self, command: NormalModeCommand) -> bool {
-        match command {
-            NormalModeCommand::Quit => return Self::exit(),
-            NormalModeCommand::MoveDown => self.move_cur_down(),
-            NormalModeCommand::MoveUp => self.move_cur_up(),
-            NormalModeCommand::MoveLeft => self.cursor.move_x(-1),
-            NormalModeCommand::MoveRight => self.cursor.move_x(1),
-            NormalModeCommand::MoveToBottom => {
-                if let Some(nr_prefix) = self.command_parser.nr_prefix() {
-                    self.move_to_line(nr_prefix);
-                    self.command_parser.clear_nr_prefix();
-                } else {
-                    self.move_to_bottom();
-                }
-            }
-            NormalModeCommand::MoveToTop => {
-                if let Some(nr_prefix) = self.command_parser.nr_prefix() {
-                    self.move_to_line(nr_prefix);
-                    self.command_parser.clear_nr_prefix();
-                } else {
-                    self.move_to_top();
-                }
-            }
-            NormalModeCommand::EnterInsertMode => {
-                self.mode = InputMode::Insert;
-                self.changed = true;
-            }
-            NormalModeCommand::Append => {
-                self.mode = InputMode::Insert;
-                self.cursor.move_x(1);
-                self.changed = true;
-            }
-            NormalModeCommand::DeleteLine => {
-                let cl = self.curr_line_nr();
-                self.content
-                    .drain(cl..cl + self.command_parser.nr_prefix().unwrap_or(1));
-                self.command_parser.clear_nr_prefix();
-            }
-            NormalModeCommand::AddLineBottom => {
-                for i in self.curr_line_nr()
-                    ..self.curr_line_nr() + self.command_parser.nr_prefix().unwrap_or(1)
-                {
-                    self.content.insert(i + 1, Line::new());
-                    self.move_cur_down();
-                }
-                self.command_parser.clear_nr_prefix();
-            }
-            NormalModeCommand::AddLineTop => {
-                for i in self.curr_line_nr()
-                    ..self.curr_line_nr() + self.command_parser.nr_prefix().unwrap_or(1)
-                {
-                    self.content.insert(i, Line::new());
-                }
-                self.command_parser.clear_nr_prefix();
+    //Removes the lines at the content indices, which have to be in order
+    fn remove_lines(&mut self, lines: &[usize]) {
+        for idx in lines.iter().rev() {
+            self.content.remove(*idx);
+        }
+        for indices in [&mut self.view, &mut self.folds] {
+            indices.retain(|idx| lines.binary_search(idx).is_err());
+            for idx in indices.iter_mut() {
+                *idx -= lines.partition_point(|line| line < idx);
             }
-            NormalModeCommand::NextWord => {
-                for _ in 0..self.command_parser.nr_prefix().unwrap_or(1) {
-                    let curr_line = self.curr_line();
-                    let mut idx = None;
-                    let mut i = 1usize;
-                    // panic!("{:?}", curr_line.content[self.cursor.x..].chars());
-                    for ch in curr_line.content[self.cursor.x..].chars() {
-                        if ch.is_ascii_punctuation() || ch == ' ' {
-                            idx = Some(i);
-                            if i + self.cursor.x >= curr_line.len() - 1 {
-                                self.move_cur_down();
-                                self.cursor.x = 0;
-                                idx = None;
-                            }
-                            break;
+        }
+        let marks = self.shift_positions(&LineShift::Remove {
+            lines: lines.to_vec(),
+            marks: Vec::new(),
+        });
+        self.line_shifts.push(LineShift::Remove {
+            lines: lines.to_vec(),
+            marks,
+        });
+    }
+
+    //Moves the marks and the jumps along with the lines, returns the marks removed with them
+    fn shift_positions(&mut self, shift: &LineShift) -> Vec<(char, (usize, usize))> {
+        let mut removed = Vec::new();
+        match shift {
+            LineShift::Insert { lines, marks } => {
+                for (line, _) in self.marks.values_mut().chain(&mut self.jumps) {
+                    for new in lines {
+                        if new <= line {
+                            *line += 1;
                         }
-                        i += 1;
-                    }
-                    if let Some(idx) = idx {
-                        self.cursor.x += idx;
                     }
                 }
-                self.command_parser.clear_nr_prefix();
+                self.marks.extend(marks.iter().copied());
             }
-            NormalModeCommand::PrevWord => {
-                for _ in 0..self.command_parser.nr_prefix().unwrap_or(1) {
-                    if self.cursor.x == 0 && self.curr_line_nr() != 0 {
-                        self.move_cur_up();
-                        self.cursor.x = self.curr_line_len() - 1;
-                    }
-                    let curr_line = self.curr_line();
-                    let mut idx = None;
-                    let mut i = 0usize;
-
-                    // panic!(
-                    //     "{} {:?}",
-                    //     self.cursor.x,
-                    //     curr_line.content[..self.cursor.x].chars().rev()
-                    // );
-
-                    for ch in curr_line.content[..self.cursor.x].chars().rev() {
-                        if (ch.is_ascii_punctuation() || ch == ' ') && i != 0 {
-                            idx = Some(i);
-                            // if self.cursor.x - i <= 0 {
-                            //     self.move_cur_up();
-                            //     self.cursor.x = self.curr_line_len() - 1;
-                            //     idx = None;
-                            // }
-                            break;
-                        }
-                        i += 1;
-                    }
-                    if let Some(idx) = idx {
-                        self.cursor.x -= idx;
-                    } else {
-                        self.cursor.x = 0;
+            //Like in vim the marks on the lines go away with them, undo brings them back
+            LineShift::Remove { lines, .. } => {
+                self.marks.retain(|name, position| {
+                    let keep = lines.binary_search(&position.0).is_err();
+                    if !keep {
+                        removed.push((*name, *position));
                     }
+                    keep
+                });
+                self.jumps
+                    .retain(|(line, _)| lines.binary_search(line).is_err());
+                self.jump_idx = self.jump_idx.min(self.jumps.len());
+                for (line, _) in self.marks.values_mut().chain(&mut self.jumps) {
+                    *line -= lines.partition_point(|idx| idx < line);
                 }
-                self.command_parser.clear_nr_prefix();
             }
-            NormalModeCommand::ToBeg => self.cursor.x = 0,
-            NormalModeCommand::ToEnd => self.cursor.x = self.curr_line_len(),
-            NormalModeCommand::DeleteChar => {
-                let nr = self.curr_line_nr();
-                let curr = self.content.get_mut(nr).unwrap();
-                for _ in 0..self.command_parser.nr_prefix().unwrap_or(1) {
-                    if curr.content.is_empty() {
-                        break;
+            LineShift::Reorder(moved) => {
+                for (line, _) in self.marks.values_mut().chain(&mut self.jumps) {
+                    if let Some(new) = moved.get(*line) {
+                        *line = *new;
                     }
-                    curr.content.remove(self.cursor.x);
                 }
-                self.command_parser.clear_nr_prefix();
             }
-
-            _ => {}
         }
-        true
+        removed
     }
 
-    fn handle_insert_mode_command(&mut self, command: InsertModeCommand) {
-        match command {
-            InsertModeCommand::EnterNormalMode => {
-                self.mode = InputMode::Normal;
-                self.cursor.move_x(-1);
-                self.changed = true;
+    fn fold(&mut self, command: NormalModeCommand) {
+        let levels = tree::levels(&self.content);
+        let line = self.curr_line_nr();
+        let folded = self.folds.binary_search(&line);
+        match (command, folded) {
+            (NormalModeCommand::OpenFold | NormalModeCommand::ToggleFold, Ok(pos)) => {
+                self.folds.remove(pos);
             }
-            InsertModeCommand::Backspace => {
-                let cursor_x = self.cursor.x;
-                if cursor_x != 0 {
-                    let curr = self.curr_line_mut();
-                    if cursor_x - 1 < curr.len() {
-                        curr.content.remove(cursor_x - 1);
-                        self.cursor.move_x(-1);
-                    }
-                }
+            (NormalModeCommand::OpenFold, Err(_)) => {
+                self.message = Some(String::from("No fold found"));
             }
-            InsertModeCommand::Delete => {
-                let cursor_x = self.cursor.x;
-                let curr = self.curr_line_mut();
-                if cursor_x < curr.len() {
-                    curr.content.remove(cursor_x);
+            //On a line without subtasks the subtree it's in is folded
+            (NormalModeCommand::CloseFold | NormalModeCommand::ToggleFold, _) => {
+                let parent = if tree::has_children(&levels, line) {
+                    Some(line)
+                } else {
+                    tree::parent(&levels, line)
+                };
+                match parent {
+                    Some(parent) => {
+                        if let Err(pos) = self.folds.binary_search(&parent) {
+                            self.folds.insert(pos, parent);
+                        }
+                    }
+                    None => self.message = Some(String::from("No fold found")),
                 }
             }
-            //Arrow Up
-            InsertModeCommand::MoveUp => {
-                self.move_cur_up();
-            }
-            //Arrow Down
-            InsertModeCommand::MoveDown => {
-                self.move_cur_down();
-            }
-            //Arrow Left
-            InsertModeCommand::MoveLeft => {
-                self.cursor.move_x(-1);
-            }
-            //Arrow Right
-            InsertModeCommand::MoveRight => {
-                self.cursor.move_x(1);
-            }
-            InsertModeCommand::Insert(c) => {
-                let cursor_x = self.cursor.x;
-                let curr = self.curr_line_mut();
-                curr.content.insert(cursor_x, c);
-                self.cursor.move_x(1);
+            (NormalModeCommand::OpenAllFolds, _) => self.folds.clear(),
+            (NormalModeCommand::CloseAllFolds, _) => {
+                self.folds = (0..self.content.len())
+                    .filter(|idx| tree::has_children(&levels, *idx))
+                    .collect();
             }
+            _ => {}
         }
+        self.refresh_view();
+        self.show_line(line);
     }
 
-    //Return false to exit.
-    fn handle_character(&mut self, buffer: Buffer) -> bool {
-        if let Some(ch) = char_parser::parse_char(buffer) {
-            match self.mode {
-                InputMode::Normal => {
-                    if let Some(command) = self.command_parser.parse_normal_mode_command(ch) {
-                        return self.handle_normal_mode_command(command);
-                    }
-                }
-                InputMode::Insert => {
-                    if let Some(command) = self.command_parser.parse_insert_mode_command(ch) {
-                        self.handle_insert_mode_command(command);
-                    }
-                }
-            }
+    //Folded lines go along with their parent
+    fn with_folded(&self, lines: &[usize]) -> Vec<usize> {
+        let folded: Vec<usize> = lines
+            .iter()
+            .copied()
+            .filter(|idx| self.folds.binary_search(idx).is_ok())
+            .collect();
+        let mut all = tree::with_subtrees(&tree::levels(&self.content), &folded);
+        all.extend_from_slice(lines);
+        all.sort_unstable();
+        all.dedup();
+        all
+    }
+
+    fn exit(&mut self) -> bool {
+        self.keep_marks();
+        //Here we don't care if we succed or not.
+        let _ = self.backend.clear().and_then(|_| self.backend.flush());
+        self.frame = None;
+        false
+    }
+
+    //Refuses to drop unsaved changes unless forced
+    fn quit(&mut self, force: bool) -> bool {
+        if !force && self.is_modified() {
+            self.message = Some(ExCommandError::NoWriteSinceLastChange.to_string());
             true
         } else {
-            true
+            self.exit()
         }
     }
 
-    pub fn update(&mut self) -> Result<bool, Box<dyn Error>> {
-        // let mut buf = Vec::new();
-        // std::io::stdin().(&mut buf)?;
-        // println!("{:?}", buf);
-        let mut buffer = [0, 0, 0, 0];
-        let mut stdin = io::stdin();
-
-        // stdout.lock().flush().unwrap();
-
-        stdin.read(&mut buffer)?;
-        if !self.handle_character(buffer) {
-            return Ok(false);
+    fn move_cur_down(&mut self) {
+        if self.cursor.y >= self.start_scroll_down && self.scroll_end < self.view.len() {
+            if self.scroll_beg < self.scroll_end {
+                self.scroll_beg += 1;
+            }
+            self.scroll_end += 1;
+        } else if self.curr_row() < self.view.len() - 1 {
+            self.cursor.move_y(1);
         }
-        let x = char::from_u32(u32::from_le_bytes(buffer));
-        // panic!("[DEBUG PANIC] Buffer: {:?}, char: {:?}", buffer, x);
-        self.bottom_line = Some(Line::from(format!(
-            "{}{}{} Line: {} {:?} Buffer: {:?}, char: {:?}",
-            termion::style::Bold,
-            match self.mode {
-                InputMode::Normal => "NORMAL",
-                InputMode::Insert => "INSERT",
-            },
-            termion::style::Reset,
-            self.curr_line_nr(),
-            self.cursor,
-            buffer,
-            x
-        )));
-        self.align_cursor();
-        // println!("{buffer:?}");
-        self.changed = true;
-        // std::thread::sleep_ms(100);
-        Ok(true)
     }
-
-    pub fn render(&mut self) -> Result<(), Box<dyn Error>> {
-        if !self.changed {
-            return Ok(());
+    fn move_cur_up(&mut self) {
+        if self.cursor.y == self.start_scroll_up && self.scroll_beg > 0
+            || (self.cursor.y == 0 && self.scroll_beg != 0)
+        {
+            self.scroll_beg -= 1;
+            if self.scroll_end > 0 {
+                self.scroll_end -= 1;
+            }
+        } else {
+            self.cursor.move_y(-1);
         }
-        self.changed = false;
-        Self::clear();
-
-        let start = self.scroll_beg;
-        // .min(self.scroll_end - START_SCROLL_UP as usize);
-        let end = self.scroll_end.min(self.content.len());
+    }
 
-        if let Some(bl) = &self.bottom_line {
-            for c in &self.content[start..end - 1] {
-                c.render();
+    //j and k, with soft wrap they move over the rows of a wrapped line before the next line
+    fn move_row(&mut self, down: bool) {
+        if !self.wrap {
+            if down {
+                self.move_cur_down();
+            } else {
+                self.move_cur_up();
             }
-            bl.render();
+            return;
+        }
+        let width = self.backend.size().map_or(80, |s| s.0 as usize).max(1);
+        let line = self.curr_line();
+        let starts = text::wrap(&line, width);
+        let (row, col) = text::wrapped_position(&line, self.cursor.x, width);
+        let row = row.min(starts.len() - 1);
+        let target = if down && row + 1 < starts.len() {
+            Some((row + 1, line))
+        } else if !down && row > 0 {
+            Some((row - 1, line))
         } else {
-            for c in &self.content[start..end] {
-                c.render();
+            let row = self.curr_row();
+            if down && row + 1 < self.view.len() {
+                self.move_cur_down();
+                Some((0, self.curr_line()))
+            } else if !down && row > 0 {
+                self.move_cur_up();
+                let line = self.curr_line();
+                Some((text::wrap(&line, width).len() - 1, line))
+            } else {
+                None
             }
+        };
+        //The same column in the other row, but not past its end
+        if let Some((row, line)) = target {
+            let starts = text::wrap(&line, width);
+            let end = starts
+                .get(row + 1)
+                .map_or(text::len(&line), |next| next - 1);
+            let start = starts[row];
+            let row_text = text::slice(&line, start, end + 1);
+            self.cursor.x = (start + text::at_column(row_text, col)).min(end);
         }
-        self.cursor.render(&self.mode);
-        Self::flush()?;
-        Ok(())
     }
-}
 
-mod test {
+    fn move_to_top(&mut self) {
+        self.scroll_beg = 0;
+        self.scroll_end = self.view_rows;
+        self.cursor.y = 0;
+    }
 
-    #[cfg(test)]
-    use super::MinTodo;
-    #[cfg(test)]
-    use crate::{
-        command_parser::{InsertModeCommand, NormalModeCommand},
-        min_todo::Line,
-    };
+    fn move_to_bottom(&mut self) {
+        self.scroll_end = self.view.len().max(self.view_rows);
+        self.scroll_beg = self.scroll_end - self.view_rows;
+        self.cursor.y = self.view.len() - 1 - self.scroll_beg;
+    }
 
-    #[test]
-    fn movement_nomal_mode() {
-        let mut mt = MinTodo::new();
-        mt.add_line(Line::from("Line of length 17"));
-        mt.add_line(Line::from("Line of length 17"));
+    //The row of the view the cursor is on
+    fn curr_row(&self) -> usize {
+        self.cursor.y + self.scroll_beg
+    }
 
-        mt.handle_normal_mode_command(NormalModeCommand::MoveDown);
-        assert_eq!(mt.curr_line_nr(), 1);
+    //The index in the content of the line the cursor is on
+    fn curr_line_nr(&self) -> usize {
+        self.view[self.curr_row()]
+    }
 
-        mt.handle_normal_mode_command(NormalModeCommand::MoveUp);
-        assert_eq!(mt.curr_line_nr(), 0);
+    //In graphemes, like the cursor position
+    fn curr_line_len(&self) -> usize {
+        text::len(&self.curr_line())
+    }
 
-        mt.handle_normal_mode_command(NormalModeCommand::MoveRight);
-        assert_eq!(mt.cursor.x, 1);
+    //The current item as it is written in the file
+    fn curr_line(&self) -> String {
+        self.format()
+            .serialize(self.content.get(self.curr_line_nr()).unwrap())
+    }
 
-        mt.handle_normal_mode_command(NormalModeCommand::MoveLeft);
-        assert_eq!(mt.cursor.x, 0);
+    //Edit the text of the current item, it's parsed again afterwards
+    fn edit_curr_line<F: FnOnce(&mut String) -> R, R>(&mut self, f: F) -> R {
+        self.edit_line(self.curr_line_nr(), f)
+    }
 
-        mt.handle_normal_mode_command(NormalModeCommand::MoveLeft);
-        assert_eq!(mt.cursor.x, 0);
+    fn edit_line<F: FnOnce(&mut String) -> R, R>(&mut self, idx: usize, f: F) -> R {
+        let format = self.format();
+        let mut line = format.serialize(&self.content[idx]);
+        let ret = f(&mut line);
+        let item = format.parse_line(&line);
+        if item != self.content[idx] {
+            self.content[idx] = item;
+            self.set_modified();
+        }
+        ret
+    }
 
-        mt.handle_normal_mode_command(NormalModeCommand::ToEnd);
-        assert_eq!(mt.cursor.x, 17);
+    //Applies `f` to `count` items starting at the current line, notes are skipped
+    fn update_items<F: FnMut(&mut TodoItem)>(&mut self, count: usize, mut f: F) {
+        let start = self.curr_row();
+        let end = (start + count).min(self.view.len());
+        let mut changed = false;
+        for idx in self.view[start..end].iter().copied() {
+            let item = &mut self.content[idx];
+            if item.kind != ItemKind::Task {
+                continue;
+            }
+            let before = item.clone();
+            f(item);
+            changed |= *item != before;
+        }
+        if changed {
+            self.set_modified();
+        }
+    }
 
-        mt.handle_normal_mode_command(NormalModeCommand::ToBeg);
+    //Keeps the cursor on an existing line after lines were removed
+    fn fix_after_removal(&mut self) {
+        if self.content.is_empty() {
+            self.insert_lines(0, vec![TodoItem::default()]);
+        }
+        //All the lines matching the filter are gone
+        if self.view.is_empty() {
+            self.refresh_view();
+        }
+        while self.curr_row() >= self.view.len() {
+            self.move_cur_up();
+        }
+    }
+
+    //Sorts the tasks in the rows the count covers, or in all of them without one.
+    //Notes and the lines hidden by the filter stay where they are.
+    fn sort(&mut self, keys: &[SortKey], reverse: bool) {
+        let keys = if keys.is_empty() {
+            &sort::DEFAULT_KEYS[..]
+        } else {
+            keys
+        };
+        let rows = match self.ex_count {
+            Some(count) => self.curr_row()..(self.curr_row() + count).min(self.view.len()),
+            None => 0..self.view.len(),
+        };
+        //The subtasks go along, hidden or not
+        let lines = tree::with_subtrees(&tree::levels(&self.content), &self.view[rows]);
+        let mut selected = vec![false; self.content.len()];
+        for idx in lines {
+            selected[idx] = true;
+        }
+        let order = sort::order(&self.content, &selected, keys, reverse);
+        if order.iter().enumerate().all(|(new, old)| new == *old) {
+            return;
+        }
+        self.content = order.iter().map(|idx| self.content[*idx].clone()).collect();
+        //Folds and marks stay with their lines
+        let mut moved = vec![0; order.len()];
+        for (new, old) in order.into_iter().enumerate() {
+            moved[old] = new;
+        }
+        for idx in &mut self.folds {
+            *idx = moved[*idx];
+        }
+        self.folds.sort_unstable();
+        let shift = LineShift::Reorder(moved);
+        self.shift_positions(&shift);
+        self.line_shifts.push(shift);
+        self.refresh_view();
+        self.set_modified();
+    }
+
+    fn archive(&mut self, count: usize) -> Result<(), Box<dyn Error>> {
+        let file_manager = self
+            .file_manager
+            .as_ref()
+            .ok_or(ExCommandError::NoFileName)?;
+        let start = self.curr_row();
+        let end = (start + count).min(self.view.len());
+        let lines: Vec<usize> = self.view[start..end]
+            .iter()
+            .copied()
+            .filter(|idx| self.content[*idx].kind == ItemKind::Task)
+            .collect();
+        if lines.is_empty() {
+            return Ok(());
+        }
+        //The subtasks of a folded item go with it
+        let lines = self.with_folded(&lines);
+        self.message = Some(format!(
+            "{} item(s) archived, written to \"{}\" with the file",
+            lines.len(),
+            file_manager.archive_path().display()
+        ));
+        self.archived
+            .extend(lines.iter().map(|idx| self.content[*idx].clone()));
+        self.remove_lines(&lines);
+        self.fix_after_removal();
+        self.set_modified();
+        Ok(())
+    }
+
+    fn enter_command_mode(&mut self, text: &str, count: Option<usize>) {
+        self.ex_count = count;
+        self.command_line.set_text(text);
+        self.mode = InputMode::Command;
+    }
+
+    fn view_state(&self) -> ViewState {
+        ViewState {
+            cursor_x: self.cursor.x,
+            cursor_y: self.cursor.y,
+            scroll_beg: self.scroll_beg,
+            scroll_end: self.scroll_end,
+        }
+    }
+
+    fn restore_view_state(&mut self, view: ViewState) {
+        self.cursor.x = view.cursor_x;
+        self.cursor.y = view.cursor_y;
+        self.scroll_beg = view.scroll_beg;
+        self.scroll_end = view.scroll_end;
+        //The terminal may have been resized since
+        self.fit_view();
+    }
+
+    //Records the change group if there is one, the whole insert session (or command line)
+    //counts as one, so nothing is recorded until we are back in normal mode
+    fn commit_undo(&mut self) {
+        if self.mode != InputMode::Normal || self.playing > 0 {
+            return;
+        }
+        if let Some(before) = self.undo_start.take() {
+            let shifts = std::mem::take(&mut self.line_shifts);
+            self.undo_tree.commit(
+                &self.content,
+                &self.archived,
+                &shifts,
+                before,
+                self.view_state(),
+            );
+        }
+    }
+
+    fn undo(&mut self, count: usize, redo: bool) {
+        for _ in 0..count {
+            let state = if redo {
+                self.undo_tree.redo()
+            } else {
+                self.undo_tree.undo()
+            };
+            let Some((snapshot, view, shifts)) = state else {
+                self.message = Some(String::from(if redo {
+                    "Already at newest change"
+                } else {
+                    "Already at oldest change"
+                }));
+                break;
+            };
+            for shift in &shifts {
+                self.shift_positions(shift);
+            }
+            self.content = snapshot.content;
+            self.archived = snapshot.archived;
+            self.refresh_view();
+            self.restore_view_state(view);
+        }
+        self.modified = !self.undo_tree.is_saved();
+    }
+
+    fn next_word(&mut self) {
+        let curr_line = self.curr_line();
+        let graphemes = text::graphemes(&curr_line);
+        let mut idx = None;
+        for (i, g) in (1usize..).zip(graphemes.iter().skip(self.cursor.x)) {
+            if text::is_separator(g) {
+                idx = Some(i);
+                if i + self.cursor.x >= graphemes.len() - 1 {
+                    self.move_cur_down();
+                    self.cursor.x = 0;
+                    idx = None;
+                }
+                break;
+            }
+        }
+        if let Some(idx) = idx {
+            self.cursor.x += idx;
+        }
+    }
+
+    fn prev_word(&mut self) {
+        if self.cursor.x == 0 && self.curr_row() != 0 {
+            self.move_cur_up();
+            self.cursor.x = self.curr_line_len().saturating_sub(1);
+        }
+        let curr_line = self.curr_line();
+        let graphemes = text::graphemes(&curr_line);
+        let mut idx = None;
+        let before = &graphemes[..self.cursor.x.min(graphemes.len())];
+        for (i, g) in before.iter().rev().enumerate() {
+            if text::is_separator(g) && i != 0 {
+                idx = Some(i);
+                break;
+            }
+        }
+        if let Some(idx) = idx {
+            self.cursor.x -= idx;
+        } else {
+            self.cursor.x = 0;
+        }
+    }
+
+    //Moves to a row of the view, scrolling like j and k do
+    fn goto_row(&mut self, row: usize) {
+        let row = row.min(self.view.len() - 1);
+        while self.curr_row() > row {
+            self.move_cur_up();
+        }
+        while self.curr_row() < row {
+            self.move_cur_down();
+        }
+    }
+
+    //Goes to a line and column kept in a mark or the jump list
+    fn goto_position(&mut self, (line, x): (usize, usize)) {
+        self.goto_row(self.row_of(line));
+        self.cursor.x = x.min(self.curr_line_len());
+    }
+
+    //Before a jump, so Ctrl-O can come back. There's only one entry for a line.
+    fn push_jump(&mut self) {
+        let line = self.curr_line_nr();
+        self.jumps.retain(|(jump, _)| *jump != line);
+        self.jumps.push((line, self.cursor.x));
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.jump_idx = self.jumps.len();
+    }
+
+    //Ctrl-O and Ctrl-I, count entries back or forward in the jump list
+    fn jump(&mut self, back: bool, count: usize) {
+        //Leaving the newest position it's remembered too, so Ctrl-I can return to it
+        if back && self.jump_idx == self.jumps.len() {
+            self.push_jump();
+            self.jump_idx -= 1;
+        }
+        let target = if back {
+            self.jump_idx.checked_sub(count)
+        } else {
+            Some(self.jump_idx + count).filter(|idx| *idx < self.jumps.len())
+        };
+        if let Some(target) = target {
+            self.jump_idx = target;
+            self.goto_position(self.jumps[target]);
+        }
+    }
+
+    fn mark(&mut self, name: char) -> Option<(usize, usize)> {
+        let mark = self.marks.get(&name).copied();
+        if mark.is_none() {
+            self.message = Some(String::from("Mark not set"));
+        }
+        mark
+    }
+
+    //The row and column a motion moves to, without actually moving
+    fn motion_target(&mut self, motion: Motion, count: Option<usize>) -> (usize, usize) {
+        let view = self.view_state();
+        let (line, x) = (self.curr_row(), self.cursor.x);
+        let last = self.view.len() - 1;
+        match motion {
+            Motion::Line => ((line + count.unwrap_or(1) - 1).min(last), x),
+            Motion::Down => ((line + count.unwrap_or(1)).min(last), x),
+            Motion::Up => (line.saturating_sub(count.unwrap_or(1)), x),
+            Motion::ToBottom => (count.map_or(last, |nr| nr.max(1) - 1).min(last), x),
+            Motion::ToTop => (count.map_or(0, |nr| nr.max(1) - 1).min(last), x),
+            Motion::ToEnd => (line, self.curr_line_len()),
+            Motion::ToBeg => (line, 0),
+            Motion::Left => (line, x.saturating_sub(count.unwrap_or(1))),
+            Motion::Right => (line, (x + count.unwrap_or(1)).min(self.curr_line_len())),
+            Motion::SearchNext | Motion::SearchPrev => {
+                let forward = self.search_forward == (motion == Motion::SearchNext);
+                self.search_target(forward, count.unwrap_or(1))
+                    .unwrap_or((line, x))
+            }
+            //Only known once the pattern is typed, operate waits for it
+            Motion::Search { .. } => (line, x),
+            //' goes to the first character that isn't a space
+            Motion::Mark { name, linewise } => match self.mark(name) {
+                Some((mark, mark_x)) => {
+                    let row = self.row_of(mark);
+                    let x = if linewise {
+                        let text = &self.serialize_lines(&[self.view[row]])[0];
+                        text::len(text) - text::len(text.trim_start())
+                    } else {
+                        mark_x
+                    };
+                    (row, x)
+                }
+                None => (line, x),
+            },
+            Motion::NextWord | Motion::PrevWord => {
+                for _ in 0..count.unwrap_or(1) {
+                    if motion == Motion::NextWord {
+                        self.next_word();
+                    } else {
+                        self.prev_word();
+                    }
+                }
+                let target = (self.curr_row(), self.cursor.x);
+                self.restore_view_state(view);
+                target
+            }
+        }
+    }
+
+    fn operate(&mut self, operation: Operation) {
+        let Operation {
+            operator,
+            motion,
+            count,
+        } = operation;
+        let register = self.command_parser.register();
+        if let Motion::Search { forward } = motion {
+            self.open_search(forward, None, Some((operation, register)));
+            return;
+        }
+        //Like in vim a missing mark doesn't leave the operator anything to work on
+        if let Motion::Mark { name, .. } = motion {
+            if self.mark(name).is_none() {
+                return;
+            }
+        }
+        let line = self.curr_row();
+        let (target_line, target_x) = self.motion_target(motion, count);
+
+        //A match or a mark on another line takes the whole lines, characterwise ranges
+        //don't go over more than one line
+        let linewise = matches!(
+            motion,
+            Motion::Line
+                | Motion::Down
+                | Motion::Up
+                | Motion::ToBottom
+                | Motion::ToTop
+                | Motion::Mark { linewise: true, .. }
+        ) || matches!(
+            motion,
+            Motion::SearchNext | Motion::SearchPrev | Motion::Mark { .. }
+        ) && target_line != line;
+        if linewise || matches!(operator, Operator::Indent | Operator::Outdent) {
+            let start = line.min(target_line);
+            let end = line.max(target_line) + 1;
+            self.operate_lines(operator, start, end, register);
+            return;
+        }
+
+        //Characterwise motions stay on the current line
+        let len = self.curr_line_len();
+        let (from, to) = match target_line.cmp(&line) {
+            Ordering::Greater => (self.cursor.x, len),
+            Ordering::Less => (0, self.cursor.x),
+            Ordering::Equal => (self.cursor.x.min(target_x), self.cursor.x.max(target_x)),
+        };
+        let (from, mut to) = (from.min(len), to.min(len));
+        let line_text = self.curr_line();
+        //Like in vim cw only changes the word, not the space after it
+        if operator == Operator::Change
+            && motion == Motion::NextWord
+            && !text::slice(&line_text, from, len).starts_with(' ')
+        {
+            to = from + text::len(text::slice(&line_text, from, to).trim_end_matches(' '));
+        }
+        self.operate_chars(operator, from, to, register);
+    }
+
+    //Applies an operator to the characters from..to of the current line
+    fn operate_chars(
+        &mut self,
+        operator: Operator,
+        from: usize,
+        to: usize,
+        register: Option<char>,
+    ) {
+        let len = self.curr_line_len();
+        let (from, to) = (from.min(len), to.min(len));
+        let text = text::slice(&self.curr_line(), from, to).to_string();
+        match operator {
+            Operator::Yank => self.registers.yank(register, Register::Charwise(text)),
+            Operator::Delete | Operator::Change => {
+                if !text.is_empty() {
+                    self.edit_curr_line(|line| text::replace_range(line, from, to, ""));
+                    self.registers.delete(register, Register::Charwise(text));
+                }
+                if operator == Operator::Change {
+                    self.mode = InputMode::Insert;
+                }
+            }
+            Operator::ToggleCase => {
+                self.edit_curr_line(|line| {
+                    text::replace_range(line, from, to, &text::toggle_case(&text))
+                });
+            }
+            //Shifting always works on whole lines
+            Operator::Indent | Operator::Outdent => {
+                let line = self.curr_row();
+                self.operate_lines(operator, line, line + 1, register);
+            }
+        }
+        self.cursor.x = from;
+    }
+
+    //Applies an operator to the lines shown in the rows start..end, and to the subtrees
+    //folded under them. Lines hidden by the filter are left alone.
+    fn operate_lines(
+        &mut self,
+        operator: Operator,
+        start: usize,
+        end: usize,
+        register: Option<char>,
+    ) {
+        let lines = self.with_folded(&self.view[start..end]);
+        let mut x = 0;
+        match operator {
+            Operator::Yank => {
+                let serialized = self.serialize_lines(&lines);
+                self.registers
+                    .yank(register, Register::Linewise(serialized));
+            }
+            Operator::Delete | Operator::Change => {
+                let serialized = self.serialize_lines(&lines);
+                //Keep a single line to type into, with the same indentation
+                let indent: String = serialized[0]
+                    .chars()
+                    .take_while(|c| c.is_whitespace())
+                    .collect();
+                self.registers
+                    .delete(register, Register::Linewise(serialized));
+                self.remove_lines(&lines);
+                if operator == Operator::Change {
+                    self.insert_lines(lines[0], vec![self.format().parse_line(&indent)]);
+                    self.mode = InputMode::Insert;
+                    x = text::len(&indent);
+                }
+                self.fix_after_removal();
+                self.set_modified();
+            }
+            //Subtasks move along with their parent, so the tree keeps its shape
+            Operator::Indent => {
+                for idx in tree::with_subtrees(&tree::levels(&self.content), &lines) {
+                    self.edit_line(idx, |line| {
+                        if !line.is_empty() {
+                            line.insert_str(0, SHIFT_WIDTH)
+                        }
+                    });
+                }
+            }
+            Operator::ToggleCase => {
+                for idx in lines {
+                    self.edit_line(idx, |line| *line = text::toggle_case(line));
+                }
+            }
+            Operator::Outdent => {
+                for idx in tree::with_subtrees(&tree::levels(&self.content), &lines) {
+                    self.edit_line(idx, |line| {
+                        let indent = line.len() - line.trim_start_matches(' ').len();
+                        if line.starts_with('\t') {
+                            line.remove(0);
+                        } else {
+                            line.replace_range(..indent.min(SHIFT_WIDTH.len()), "");
+                        }
+                    });
+                }
+            }
+        }
+        self.goto_row(start);
+        self.cursor.x = x;
+    }
+
+    //What a line starts with before its text: the indent, and in markdown the list marker.
+    //A line split off a task starts the same way, so it stays at the same level.
+    fn line_prefix(&self, item: &TodoItem, done: bool) -> String {
+        match item.kind {
+            ItemKind::Task => self.format().serialize(&TodoItem {
+                indent: item.indent.clone(),
+                bullet: item.bullet,
+                done,
+                ..TodoItem::default()
+            }),
+            ItemKind::Note => {
+                let text = item.description.trim_start_matches([' ', '\t']);
+                item.description[..item.description.len() - text.len()].to_string()
+            }
+        }
+    }
+
+    //Moves the text after the cursor to a new line below, as an open task at the same level
+    fn split_line(&mut self) {
+        let idx = self.curr_line_nr();
+        let format = self.format();
+        let line = format.serialize(&self.content[idx]);
+        let prefix = self.line_prefix(&self.content[idx], false);
+        let (before, after) = (
+            text::slice(&line, 0, self.cursor.x).to_string(),
+            text::slice(&line, self.cursor.x, usize::MAX),
+        );
+        //Before the text the whole line moves down
+        let new_line = if self.cursor.x < text::len(&prefix) {
+            after.to_string()
+        } else {
+            format!("{prefix}{}", after.trim_start())
+        };
+        self.edit_line(idx, |line| *line = before);
+        self.insert_lines(idx + 1, vec![format.parse_line(&new_line)]);
+        self.move_cur_down();
+        self.cursor.x = text::len(&new_line) - text::len(after.trim_start());
+        self.set_modified();
+    }
+
+    //Appends the text of the lines in the next rows to the current one, separated by a space.
+    //Their indent and list marker are dropped.
+    fn join_lines(&mut self, count: usize) {
+        let start = self.curr_row();
+        let end = (start + count.max(2)).min(self.view.len());
+        if end - start < 2 {
+            return;
+        }
+        let lines = self.view[start + 1..end].to_vec();
+        let format = self.format();
+        let mut joined = format.serialize(&self.content[self.view[start]]);
+        let mut x = self.cursor.x;
+        for idx in &lines {
+            let item = &self.content[*idx];
+            let line = format.serialize(item);
+            let prefix = self.line_prefix(item, item.done);
+            let rest = line.strip_prefix(&prefix).unwrap_or(&line).trim_start();
+            x = text::len(&joined);
+            if !rest.is_empty() && !joined.is_empty() && !joined.ends_with([' ', '\t']) {
+                joined.push(' ');
+            }
+            joined.push_str(rest);
+        }
+        self.remove_lines(&lines);
+        self.edit_curr_line(|line| *line = joined);
+        self.set_modified();
+        self.fit_view();
+        self.cursor.x = x;
+    }
+
+    fn serialize_lines(&self, lines: &[usize]) -> Vec<String> {
+        let format = self.format();
+        lines
+            .iter()
+            .map(|idx| format.serialize(&self.content[*idx]))
+            .collect()
+    }
+
+    fn put(&mut self, count: usize, before: bool) {
+        let name = self.command_parser.register();
+        let Some(register) = self.registers.get(name).cloned() else {
+            self.message = Some(format!("Nothing in register {}", name.unwrap_or('"')));
+            return;
+        };
+        match register {
+            Register::Linewise(lines) => {
+                let format = self.format();
+                let items: Vec<_> = lines.iter().map(|line| format.parse_line(line)).collect();
+                let at = self.curr_line_nr() + usize::from(!before);
+                self.insert_lines(
+                    at,
+                    items
+                        .iter()
+                        .cycle()
+                        .take(items.len() * count)
+                        .cloned()
+                        .collect(),
+                );
+                //The cursor ends up on the first put line
+                if !before {
+                    self.move_cur_down();
+                }
+                self.cursor.x = 0;
+                self.set_modified();
+            }
+            Register::Charwise(text) => {
+                if text.is_empty() {
+                    return;
+                }
+                let text = text.repeat(count);
+                let x = if before {
+                    self.cursor.x
+                } else {
+                    (self.cursor.x + 1).min(self.curr_line_len())
+                };
+                let end = self.edit_curr_line(|line| text::insert(line, x, &text));
+                self.cursor.x = end - 1;
+            }
+        }
+    }
+
+    fn open_search(
+        &mut self,
+        forward: bool,
+        count: Option<usize>,
+        operation: Option<(Operation, Option<char>)>,
+    ) {
+        self.search_line.clear();
+        self.search_prompt = Some(SearchPrompt {
+            forward,
+            start: self.view_state(),
+            count,
+            operation,
+        });
+        self.mode = InputMode::Search;
+    }
+
+    //What's typed so far, None while it's empty or not a valid pattern yet
+    fn typed_search(&self) -> Option<Regex> {
+        let pattern = self.search_line.text();
+        (!pattern.is_empty())
+            .then(|| search::compile(pattern).ok())
+            .flatten()
+    }
+
+    //Moves to the first match of the pattern typed so far, or back to the start without one
+    fn search_incremental(&mut self) {
+        let Some(prompt) = &self.search_prompt else {
+            return;
+        };
+        let forward = prompt.forward;
+        self.restore_view_state(prompt.start);
+        let Some(regex) = self.typed_search() else {
+            return;
+        };
+        let lines = self.serialize_lines(&self.view);
+        if let Some(((line, x), _)) =
+            search::find(&regex, &lines, (self.curr_row(), self.cursor.x), forward)
+        {
+            self.goto_row(line);
+            self.cursor.x = x;
+        }
+    }
+
+    fn close_search(&mut self) -> Option<SearchPrompt> {
+        self.mode = InputMode::Normal;
+        let prompt = self.search_prompt.take()?;
+        self.restore_view_state(prompt.start);
+        Some(prompt)
+    }
+
+    //Enter in the search prompt, an empty pattern searches for the last one again
+    fn submit_search(&mut self) {
+        let pattern = self.search_line.submit();
+        let Some(prompt) = self.close_search() else {
+            return;
+        };
+        if !pattern.is_empty() {
+            match search::compile(&pattern) {
+                Ok(regex) => self.search = Some(regex),
+                Err(_) => {
+                    self.message = Some(format!("Invalid pattern: {pattern}"));
+                    return;
+                }
+            }
+        }
+        self.search_forward = prompt.forward;
+        match prompt.operation {
+            Some((operation, register)) => {
+                self.command_parser.set_register(register);
+                self.operate(Operation {
+                    motion: Motion::SearchNext,
+                    ..operation
+                });
+                self.command_parser.clear_register();
+            }
+            None => {
+                let (line, x) = self.motion_target(Motion::SearchNext, prompt.count);
+                self.push_jump();
+                self.goto_row(line);
+                self.cursor.x = x;
+            }
+        }
+    }
+
+    //Where the count-th match from the cursor is, with the message vim shows on the way
+    fn search_target(&mut self, forward: bool, count: usize) -> Option<(usize, usize)> {
+        let Some(regex) = self.search.clone() else {
+            self.message = Some(String::from("No previous regular expression"));
+            return None;
+        };
+        self.highlight = true;
+        let lines = self.serialize_lines(&self.view);
+        let mut position = (self.curr_row(), self.cursor.x);
+        let mut wrapped = false;
+        for _ in 0..count {
+            let Some((next, wrap)) = search::find(&regex, &lines, position, forward) else {
+                self.message = Some(format!("Pattern not found: {}", regex.as_str()));
+                return None;
+            };
+            position = next;
+            wrapped |= wrap;
+        }
+        if wrapped {
+            self.message = Some(String::from(if forward {
+                "search hit BOTTOM, continuing at TOP"
+            } else {
+                "search hit TOP, continuing at BOTTOM"
+            }));
+        }
+        Some(position)
+    }
+
+    //Start and end of the selection as rows and columns, in order, both inclusive
+    fn selection(&self) -> ((usize, usize), (usize, usize)) {
+        let cursor = (self.curr_row(), self.cursor.x);
+        (self.visual_start.min(cursor), self.visual_start.max(cursor))
+    }
+
+    //The part of a line that's highlighted as selected. A characterwise selection
+    //over several lines acts on whole lines, so that's what is shown too.
+    fn selected_span(&self, row: usize) -> Option<(usize, usize)> {
+        let ((start_line, start_x), (end_line, end_x)) = self.selection();
+        match self.mode {
+            InputMode::Visual | InputMode::VisualLine if (start_line..=end_line).contains(&row) => {
+                let len = text::len(&self.format().serialize(&self.content[self.view[row]]));
+                if self.mode == InputMode::Visual && start_line == end_line {
+                    Some((start_x.min(len), (end_x + 1).min(len)))
+                } else {
+                    Some((0, len))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn align_cursor(&mut self) {
+        //In insert mode the cursor can be right after the last character
+        let cll = if matches!(self.mode, InputMode::Insert | InputMode::Replace) {
+            self.curr_line_len()
+        } else {
+            self.curr_line_len().max(1) - 1
+        };
+        if cll < self.cursor.x {
+            self.cursor.x = cll;
+        }
+    }
+
+    //Todo commands work on `items` items from the current line, a count typed before them
+    //is the number of steps for priorities and the number of days from today for the due date
+    fn handle_todo_command(&mut self, command: NormalModeCommand, items: usize) {
+        match command {
+            NormalModeCommand::EnterCommandMode => self.enter_command_mode("", Some(items)),
+            NormalModeCommand::Sort => self.enter_command_mode("sort ", Some(items)),
+            NormalModeCommand::AddTag => self.enter_command_mode("tag ", Some(items)),
+            NormalModeCommand::RemoveTag => self.enter_command_mode("untag ", Some(items)),
+            NormalModeCommand::ToggleDone => {
+                let today = Date::today();
+                self.update_items(items, |item| item.toggle_done(today));
+            }
+            NormalModeCommand::RaisePriority | NormalModeCommand::LowerPriority => {
+                let steps = self.command_parser.nr_prefix().unwrap_or(1).min(26) as u8;
+                self.update_items(items, |item| {
+                    if command == NormalModeCommand::RaisePriority {
+                        item.raise_priority(steps)
+                    } else {
+                        item.lower_priority(steps)
+                    }
+                });
+            }
+            NormalModeCommand::SetDueDate => {
+                let days = self.command_parser.nr_prefix().unwrap_or(0);
+                let due = i64::try_from(days)
+                    .ok()
+                    .and_then(|days| Date::today().add_days(days));
+                match due {
+                    Some(due) => {
+                        let due = due.to_string();
+                        self.update_items(items, |item| item.set_meta("due", Some(&due)));
+                    }
+                    None => self.message = Some(format!("Invalid due date: +{days}")),
+                }
+            }
+            NormalModeCommand::Archive => {
+                if let Err(e) = self.archive(items) {
+                    self.message = Some(e.to_string());
+                }
+            }
+            _ => {}
+        }
+        self.command_parser.clear_nr_prefix();
+    }
+
+    fn handle_normal_mode_command(&mut self, command: NormalModeCommand) -> bool {
+        match command {
+            //Like vim, quitting is left to :q
+            NormalModeCommand::Interrupt => {
+                self.message = Some(String::from("Type :q and press <Enter> to exit"));
+            }
+            //Without a count the ex commands choose, :sort sorts everything
+            NormalModeCommand::EnterCommandMode | NormalModeCommand::Sort => {
+                let text = if command == NormalModeCommand::Sort {
+                    "sort "
+                } else {
+                    ""
+                };
+                self.enter_command_mode(text, self.command_parser.nr_prefix());
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::AddTag
+            | NormalModeCommand::RemoveTag
+            | NormalModeCommand::ToggleDone
+            | NormalModeCommand::Archive => {
+                let items = self.command_parser.nr_prefix().unwrap_or(1);
+                self.command_parser.clear_nr_prefix();
+                self.handle_todo_command(command, items);
+            }
+            NormalModeCommand::RaisePriority
+            | NormalModeCommand::LowerPriority
+            | NormalModeCommand::SetDueDate => self.handle_todo_command(command, 1),
+            NormalModeCommand::EnterVisualMode | NormalModeCommand::EnterVisualLineMode => {
+                self.visual_start = (self.curr_row(), self.cursor.x);
+                self.mode = if command == NormalModeCommand::EnterVisualMode {
+                    InputMode::Visual
+                } else {
+                    InputMode::VisualLine
+                };
+            }
+            NormalModeCommand::RecordMacro(name) => {
+                self.recording = Some((name, Vec::new()));
+                self.command_parser.set_recording(true);
+            }
+            NormalModeCommand::StopRecording => {
+                if let Some((name, mut keys)) = self.recording.take() {
+                    //The q that stopped it
+                    keys.pop();
+                    self.registers.record(name, keys);
+                }
+                self.command_parser.set_recording(false);
+            }
+            NormalModeCommand::PlayMacro(name) => {
+                let count = self.command_parser.nr_prefix().unwrap_or(1);
+                self.command_parser.clear_nr_prefix();
+                return self.play_macro(name, count);
+            }
+            NormalModeCommand::Repeat => {
+                let count = self.command_parser.nr_prefix();
+                self.command_parser.clear_nr_prefix();
+                if let Some(change) = self.last_change.clone() {
+                    self.repeat_change(change, count);
+                }
+            }
+            NormalModeCommand::Undo | NormalModeCommand::Redo => {
+                self.undo(
+                    self.command_parser.nr_prefix().unwrap_or(1),
+                    command == NormalModeCommand::Redo,
+                );
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::MoveDown | NormalModeCommand::MoveUp => {
+                for _ in 0..self.command_parser.nr_prefix().unwrap_or(1) {
+                    self.move_row(command == NormalModeCommand::MoveDown);
+                }
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::MoveLeft | NormalModeCommand::MoveRight => {
+                let count = self.command_parser.nr_prefix().unwrap_or(1);
+                let count = i32::try_from(count).unwrap_or(i32::MAX);
+                self.cursor
+                    .move_x(if command == NormalModeCommand::MoveLeft {
+                        -count
+                    } else {
+                        count
+                    });
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::MoveToBottom => {
+                self.push_jump();
+                if let Some(nr_prefix) = self.command_parser.nr_prefix() {
+                    self.goto_row(nr_prefix.max(1) - 1);
+                    self.command_parser.clear_nr_prefix();
+                } else {
+                    self.move_to_bottom();
+                }
+            }
+            NormalModeCommand::MoveToTop => {
+                self.push_jump();
+                if let Some(nr_prefix) = self.command_parser.nr_prefix() {
+                    self.goto_row(nr_prefix.max(1) - 1);
+                    self.command_parser.clear_nr_prefix();
+                } else {
+                    self.move_to_top();
+                }
+            }
+            NormalModeCommand::EnterInsertMode => {
+                self.mode = InputMode::Insert;
+                self.changed = true;
+            }
+            NormalModeCommand::Append => {
+                self.mode = InputMode::Insert;
+                self.cursor.move_x(1);
+                self.changed = true;
+            }
+            NormalModeCommand::Operate(operation) => self.operate(operation),
+            NormalModeCommand::ToggleFold
+            | NormalModeCommand::OpenFold
+            | NormalModeCommand::CloseFold
+            | NormalModeCommand::OpenAllFolds
+            | NormalModeCommand::CloseAllFolds => self.fold(command),
+            NormalModeCommand::Search { forward } => {
+                self.open_search(forward, self.command_parser.nr_prefix(), None);
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::SearchNext | NormalModeCommand::SearchPrev => {
+                let motion = if command == NormalModeCommand::SearchNext {
+                    Motion::SearchNext
+                } else {
+                    Motion::SearchPrev
+                };
+                let (line, x) = self.motion_target(motion, self.command_parser.nr_prefix());
+                self.push_jump();
+                self.goto_row(line);
+                self.cursor.x = x;
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::SetMark(name) => {
+                self.marks
+                    .insert(name, (self.curr_line_nr(), self.cursor.x));
+            }
+            NormalModeCommand::JumpToMark { name, linewise } => {
+                if self.mark(name).is_some() {
+                    let (line, x) = self.motion_target(Motion::Mark { name, linewise }, None);
+                    self.push_jump();
+                    self.goto_row(line);
+                    self.cursor.x = x;
+                }
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::JumpBack | NormalModeCommand::JumpForward => {
+                self.jump(
+                    command == NormalModeCommand::JumpBack,
+                    self.command_parser.nr_prefix().unwrap_or(1),
+                );
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::Put | NormalModeCommand::PutBefore => {
+                self.put(
+                    self.command_parser.nr_prefix().unwrap_or(1),
+                    command == NormalModeCommand::PutBefore,
+                );
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::AddLineBottom => {
+                for _ in 0..self.command_parser.nr_prefix().unwrap_or(1) {
+                    self.insert_lines(self.curr_line_nr() + 1, vec![TodoItem::default()]);
+                    self.move_cur_down();
+                }
+                self.command_parser.clear_nr_prefix();
+                self.set_modified();
+            }
+            NormalModeCommand::AddLineTop => {
+                for _ in 0..self.command_parser.nr_prefix().unwrap_or(1) {
+                    self.insert_lines(self.curr_line_nr(), vec![TodoItem::default()]);
+                }
+                self.command_parser.clear_nr_prefix();
+                self.set_modified();
+            }
+            NormalModeCommand::NextWord => {
+                for _ in 0..self.command_parser.nr_prefix().unwrap_or(1) {
+                    self.next_word();
+                }
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::PrevWord => {
+                for _ in 0..self.command_parser.nr_prefix().unwrap_or(1) {
+                    self.prev_word();
+                }
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::ToBeg => self.cursor.x = 0,
+            NormalModeCommand::ToEnd => self.cursor.x = self.curr_line_len(),
+            NormalModeCommand::DeleteChar => {
+                let cursor_x = self.cursor.x;
+                let count = self.command_parser.nr_prefix().unwrap_or(1);
+                let deleted = self.edit_curr_line(|line| {
+                    let deleted = text::slice(line, cursor_x, cursor_x + count).to_string();
+                    text::replace_range(line, cursor_x, cursor_x + count, "");
+                    deleted
+                });
+                if !deleted.is_empty() {
+                    self.registers
+                        .delete(self.command_parser.register(), Register::Charwise(deleted));
+                }
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::JoinLines => {
+                self.join_lines(self.command_parser.nr_prefix().unwrap_or(2));
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::ReplaceChar(c) => {
+                let count = self.command_parser.nr_prefix().unwrap_or(1);
+                let x = self.cursor.x;
+                //Like in vim nothing is replaced when the line is too short
+                if x + count <= self.curr_line_len() {
+                    let with = c.to_string().repeat(count);
+                    self.edit_curr_line(|line| text::replace_range(line, x, x + count, &with));
+                    self.cursor.x = x + count - 1;
+                }
+                self.command_parser.clear_nr_prefix();
+            }
+            NormalModeCommand::EnterReplaceMode => {
+                self.mode = InputMode::Replace;
+                self.replace = Some(ReplaceState {
+                    original: self.curr_line(),
+                    start: self.cursor.x,
+                    typed: String::new(),
+                    count: self.command_parser.nr_prefix().unwrap_or(1),
+                });
+                self.command_parser.clear_nr_prefix();
+            }
+            //Moves past the characters it changed
+            NormalModeCommand::ToggleCase => {
+                let x = self.cursor.x;
+                let to =
+                    (x + self.command_parser.nr_prefix().unwrap_or(1)).min(self.curr_line_len());
+                self.operate_chars(Operator::ToggleCase, x, to, None);
+                self.cursor.x = to;
+                self.command_parser.clear_nr_prefix();
+            }
+
+            _ => {}
+        }
+        true
+    }
+
+    //Return false to exit.
+    fn handle_visual_mode_command(&mut self, command: VisualModeCommand) -> bool {
+        match command {
+            VisualModeCommand::Visual | VisualModeCommand::VisualLine => {
+                let mode = if command == VisualModeCommand::Visual {
+                    InputMode::Visual
+                } else {
+                    InputMode::VisualLine
+                };
+                self.mode = if self.mode == mode {
+                    InputMode::Normal
+                } else {
+                    mode
+                };
+            }
+            VisualModeCommand::Exit => self.mode = InputMode::Normal,
+            VisualModeCommand::SwapEnds => {
+                let (line, x) = self.visual_start;
+                self.visual_start = (self.curr_row(), self.cursor.x);
+                self.goto_row(line);
+                self.cursor.x = x;
+            }
+            VisualModeCommand::Operate(operator) => {
+                let ((start_line, start_x), (end_line, end_x)) = self.selection();
+                let register = self.command_parser.register();
+                let linewise = self.mode == InputMode::VisualLine || start_line != end_line;
+                self.mode = InputMode::Normal;
+                if linewise {
+                    self.operate_lines(operator, start_line, end_line + 1, register);
+                } else {
+                    self.operate_chars(operator, start_x, end_x + 1, register);
+                }
+            }
+            VisualModeCommand::Normal(command) => match command {
+                NormalModeCommand::EnterCommandMode
+                | NormalModeCommand::Sort
+                | NormalModeCommand::AddTag
+                | NormalModeCommand::RemoveTag
+                | NormalModeCommand::ToggleDone
+                | NormalModeCommand::RaisePriority
+                | NormalModeCommand::LowerPriority
+                | NormalModeCommand::SetDueDate
+                | NormalModeCommand::Archive => {
+                    let ((start, _), (end, _)) = self.selection();
+                    self.mode = InputMode::Normal;
+                    self.goto_row(start);
+                    self.handle_todo_command(command, end - start + 1);
+                }
+                NormalModeCommand::JoinLines => {
+                    let ((start, _), (end, _)) = self.selection();
+                    self.mode = InputMode::Normal;
+                    self.goto_row(start);
+                    self.join_lines(end - start + 1);
+                }
+                NormalModeCommand::ToggleFold
+                | NormalModeCommand::OpenFold
+                | NormalModeCommand::CloseFold
+                | NormalModeCommand::OpenAllFolds
+                | NormalModeCommand::CloseAllFolds => {
+                    self.mode = InputMode::Normal;
+                    self.fold(command);
+                }
+                NormalModeCommand::MoveDown
+                | NormalModeCommand::MoveUp
+                | NormalModeCommand::MoveLeft
+                | NormalModeCommand::MoveRight
+                | NormalModeCommand::MoveToBottom
+                | NormalModeCommand::MoveToTop
+                | NormalModeCommand::NextWord
+                | NormalModeCommand::PrevWord
+                | NormalModeCommand::ToBeg
+                | NormalModeCommand::ToEnd
+                | NormalModeCommand::SearchNext
+                | NormalModeCommand::SearchPrev
+                | NormalModeCommand::RecordMacro(_)
+                | NormalModeCommand::StopRecording
+                | NormalModeCommand::SetMark(_)
+                | NormalModeCommand::JumpToMark { .. }
+                | NormalModeCommand::JumpBack
+                | NormalModeCommand::JumpForward => {
+                    return self.handle_normal_mode_command(command)
+                }
+                _ => self.command_parser.clear_nr_prefix(),
+            },
+        }
+        true
+    }
+
+    fn handle_insert_mode_command(&mut self, command: InsertModeCommand) {
+        match command {
+            InsertModeCommand::EnterNormalMode => {
+                self.mode = InputMode::Normal;
+                self.cursor.move_x(-1);
+                self.changed = true;
+            }
+            InsertModeCommand::Backspace => {
+                let cursor_x = self.cursor.x;
+                if cursor_x != 0
+                    && self.edit_curr_line(|line| {
+                        cursor_x - 1 < text::len(line) && {
+                            text::replace_range(line, cursor_x - 1, cursor_x, "");
+                            true
+                        }
+                    })
+                {
+                    self.cursor.move_x(-1);
+                }
+            }
+            InsertModeCommand::Delete => {
+                let cursor_x = self.cursor.x;
+                self.edit_curr_line(|line| text::replace_range(line, cursor_x, cursor_x + 1, ""));
+            }
+            //Arrow Up
+            InsertModeCommand::MoveUp => {
+                self.move_row(false);
+            }
+            //Arrow Down
+            InsertModeCommand::MoveDown => {
+                self.move_row(true);
+            }
+            //Arrow Left
+            InsertModeCommand::MoveLeft => {
+                self.cursor.move_x(-1);
+            }
+            //Arrow Right
+            InsertModeCommand::MoveRight => {
+                self.cursor.move_x(1);
+            }
+            InsertModeCommand::Insert(c) => {
+                let cursor_x = self.cursor.x;
+                self.cursor.x =
+                    self.edit_curr_line(|line| text::insert(line, cursor_x, &c.to_string()));
+            }
+            InsertModeCommand::NewLine => self.split_line(),
+        }
+    }
+
+    //Return false to exit, a macro can quit too
+    fn play_macro(&mut self, name: char, count: usize) -> bool {
+        let Some(name) = (if name == '@' {
+            self.last_macro
+        } else {
+            Some(name)
+        }) else {
+            self.message = Some(String::from("No previously used register"));
+            return true;
+        };
+        let Some(keys) = self.registers.get_macro(name).cloned() else {
+            self.message = Some(format!("Nothing in register {name}"));
+            return true;
+        };
+        self.last_macro = Some(name);
+        if self.playing >= MAX_MACRO_DEPTH {
+            self.message = Some(String::from("Macros nested too deep"));
+            return true;
+        }
+        self.playing += 1;
+        let mut keep_running = true;
+        for _ in 0..count {
+            for ch in keys.iter().copied() {
+                keep_running = self.handle_key(ch);
+                if !keep_running {
+                    break;
+                }
+            }
+        }
+        self.playing -= 1;
+        keep_running
+    }
+
+    //A count replaces the one the change was made with, and is used from then on
+    fn repeat_change(&mut self, change: Change, count: Option<usize>) {
+        let count = count.or(change.count);
+        let command = match change.command {
+            NormalModeCommand::Operate(operation) => {
+                NormalModeCommand::Operate(Operation { count, ..operation })
+            }
+            command => {
+                self.command_parser.set_nr_prefix(count);
+                command
+            }
+        };
+        self.command_parser.set_register(change.register);
+        self.handle_normal_mode_command(command);
+        self.command_parser.clear_nr_prefix();
+        self.command_parser.clear_register();
+        for ch in change.typed.iter().copied() {
+            self.handle_key(ch);
+        }
+        self.last_change = Some(Change { count, ..change });
+    }
+
+    //Typing overwrites the line, everything else works like in insert mode
+    fn handle_replace_mode_command(&mut self, command: InsertModeCommand) {
+        match command {
+            InsertModeCommand::Insert(c) => {
+                self.overwrite(c);
+                if let Some(state) = &mut self.replace {
+                    state.typed.push(c);
+                }
+            }
+            InsertModeCommand::Backspace => match &mut self.replace {
+                Some(state) if self.cursor.x > state.start => {
+                    state.typed.pop();
+                    let x = self.cursor.x - 1;
+                    let original = text::slice(&state.original, x, x + 1).to_string();
+                    self.edit_curr_line(|line| text::replace_range(line, x, x + 1, &original));
+                    self.cursor.x = x;
+                }
+                _ => self.cursor.move_x(-1),
+            },
+            InsertModeCommand::EnterNormalMode => {
+                if let Some(state) = self.replace.take() {
+                    for _ in 1..state.count {
+                        state.typed.chars().for_each(|c| self.overwrite(c));
+                    }
+                }
+                self.handle_insert_mode_command(command);
+            }
+            //What was overwritten before moving can't be put back
+            _ => {
+                self.handle_insert_mode_command(command);
+                self.replace = Some(ReplaceState {
+                    original: self.curr_line(),
+                    start: self.cursor.x,
+                    typed: String::new(),
+                    count: 1,
+                });
+            }
+        }
+    }
+
+    fn overwrite(&mut self, c: char) {
+        let x = self.cursor.x;
+        self.cursor.x = self.edit_curr_line(|line| {
+            text::replace_range(line, x, x + 1, "");
+            text::insert(line, x, &c.to_string())
+        });
+    }
+
+    //Return false to exit.
+    fn handle_command_mode_command(&mut self, command: CommandModeCommand) -> bool {
+        match command {
+            CommandModeCommand::EnterNormalMode => {
+                self.command_line.clear();
+                self.mode = InputMode::Normal;
+            }
+            CommandModeCommand::Execute => {
+                self.mode = InputMode::Normal;
+                let text = self.command_line.submit();
+                if text.trim().is_empty() {
+                    return true;
+                }
+                match ExCommand::parse(&text) {
+                    Ok(command) => return self.handle_ex_command(command),
+                    Err(e) => self.message = Some(e.to_string()),
+                }
+            }
+            CommandModeCommand::Insert(c) => self.command_line.insert(c),
+            CommandModeCommand::Backspace => {
+                //Like in vim, deleting past the beginning leaves the command line
+                if !self.command_line.backspace() {
+                    self.mode = InputMode::Normal;
+                }
+            }
+            CommandModeCommand::Delete => self.command_line.delete(),
+            CommandModeCommand::MoveLeft => self.command_line.move_left(),
+            CommandModeCommand::MoveRight => self.command_line.move_right(),
+            CommandModeCommand::HistoryPrev => self.command_line.history_prev(),
+            CommandModeCommand::HistoryNext => self.command_line.history_next(),
+        }
+        true
+    }
+
+    fn handle_search_mode_command(&mut self, command: CommandModeCommand) {
+        match command {
+            CommandModeCommand::EnterNormalMode => {
+                self.search_line.clear();
+                self.close_search();
+                return;
+            }
+            CommandModeCommand::Execute => {
+                self.submit_search();
+                return;
+            }
+            CommandModeCommand::Insert(c) => self.search_line.insert(c),
+            CommandModeCommand::Backspace => {
+                if !self.search_line.backspace() {
+                    self.close_search();
+                    return;
+                }
+            }
+            CommandModeCommand::Delete => self.search_line.delete(),
+            CommandModeCommand::MoveLeft => self.search_line.move_left(),
+            CommandModeCommand::MoveRight => self.search_line.move_right(),
+            CommandModeCommand::HistoryPrev => self.search_line.history_prev(),
+            CommandModeCommand::HistoryNext => self.search_line.history_next(),
+        }
+        self.search_incremental();
+    }
+
+    //Return false to exit.
+    fn handle_ex_command(&mut self, command: ExCommand) -> bool {
+        match self.execute_ex_command(command) {
+            Ok(keep_running) => keep_running,
+            Err(e) => {
+                self.message = Some(e.to_string());
+                true
+            }
+        }
+    }
+
+    fn execute_ex_command(&mut self, command: ExCommand) -> Result<bool, Box<dyn Error>> {
+        match command {
+            ExCommand::Write { path: None, force } => self.save(force)?,
+            ExCommand::Write {
+                path: Some(path),
+                force,
+            } => match &self.file_manager {
+                Some(current) if current.path() == path => self.save(force)?,
+                Some(_) => {
+                    //Write a copy, the buffer stays attached to its file
+                    let mut file_manager = FileManager::new(&path)?;
+                    if !force && file_manager.status() != FileManagerStatus::NewFile {
+                        return Err(ExCommandError::FileExists.into());
+                    }
+                    file_manager.save(&self.content, force)?;
+                    self.message = Some(format!(
+                        "\"{}\" {}L written",
+                        path.display(),
+                        self.content.len()
+                    ));
+                }
+                None => return self.execute_ex_command(ExCommand::SaveAs { path, force }),
+            },
+            ExCommand::SaveAs { path, force } => {
+                let mut file_manager = FileManager::new(path)?;
+                if !force && file_manager.status() != FileManagerStatus::NewFile {
+                    return Err(ExCommandError::FileExists.into());
+                }
+                //The buffer only moves to the new file once it's written there
+                self.write_file(&mut file_manager, force)?;
+                self.file_manager = Some(file_manager);
+            }
+            ExCommand::Quit { force } => return Ok(self.quit(force)),
+            ExCommand::WriteQuit { force } => {
+                self.save(force)?;
+                return Ok(self.exit());
+            }
+            ExCommand::Edit { path, force } => {
+                if !force && self.is_modified() {
+                    return Err(ExCommandError::NoWriteSinceLastChange.into());
+                }
+                let path = match path {
+                    Some(path) => path,
+                    None => self
+                        .file_manager
+                        .as_ref()
+                        .ok_or(ExCommandError::NoFileName)?
+                        .path()
+                        .to_path_buf(),
+                };
+                let mut file_manager = FileManager::new(path)?;
+                if let Some(current) = &self.file_manager {
+                    file_manager.set_parse_mode(current.parse_mode());
+                }
+                self.keep_marks();
+                self.load(file_manager)?;
+            }
+            ExCommand::Tag(tag) => {
+                self.update_items(self.ex_count.unwrap_or(1), |item| item.add_tag(&tag))
+            }
+            ExCommand::Untag(tag) => self.update_items(self.ex_count.unwrap_or(1), |item| {
+                item.remove_tag(&tag);
+            }),
+            ExCommand::Due(date) => {
+                let date = date.map(|d| d.to_string());
+                self.update_items(self.ex_count.unwrap_or(1), |item| {
+                    item.set_meta("due", date.as_deref())
+                })
+            }
+            ExCommand::Archive => self.archive(self.ex_count.unwrap_or(1))?,
+            ExCommand::Sort { keys, reverse } => self.sort(&keys, reverse),
+            ExCommand::Set(Setting::Wrap(wrap)) => self.wrap = wrap,
+            ExCommand::NoHighlight => self.highlight = false,
+            ExCommand::Filter(filter) => self.set_filter(filter),
+        }
+        Ok(true)
+    }
+
+    //Return false to exit.
+    fn handle_event(&mut self, event: Event) -> bool {
+        match event {
+            //Alt is sent as ESC before the key, so it behaves like typing Esc and then the key
+            Event::Key(c, modifiers) if modifiers.alt => {
+                self.record_key(Character::Esc);
+                self.record_key(c);
+                self.handle_key(Character::Esc) && self.handle_key(c)
+            }
+            Event::Key(c, _) => {
+                self.record_key(c);
+                self.handle_key(c)
+            }
+            Event::Resize => {
+                self.resize();
+                true
+            }
+            //Pasted text is only ever text, it's never run as commands
+            Event::Paste(text) => {
+                if matches!(
+                    self.mode,
+                    InputMode::Insert | InputMode::Replace | InputMode::Command | InputMode::Search
+                ) {
+                    for c in text.chars() {
+                        let key = match c {
+                            '\r' | '\n' => Character::Enter,
+                            '\t' => Character::Tab,
+                            c => Character::Display(c),
+                        };
+                        self.record_key(key);
+                        self.handle_key(key);
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    //Macros get the keys as they come in, not the ones played by . or another macro
+    fn record_key(&mut self, ch: Character) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(ch);
+        }
+    }
+
+    //Return false to exit.
+    fn handle_key(&mut self, ch: Character) -> bool {
+        if self.undo_start.is_none() {
+            self.undo_start = Some(self.view_state());
+            //Whatever moved outside of a change group has no undo step to go with
+            self.line_shifts.clear();
+        }
+        let recording = self.recording_change && self.mode != InputMode::Normal;
+        let keep_running = match self.mode {
+            InputMode::Normal => match self.command_parser.parse_normal_mode_command(ch) {
+                Some(command) => {
+                    let change = Change {
+                        command,
+                        count: match command {
+                            NormalModeCommand::Operate(operation) => operation.count,
+                            _ => self.command_parser.nr_prefix(),
+                        },
+                        register: self.command_parser.register(),
+                        typed: Vec::new(),
+                    };
+                    let keep_running = self.handle_normal_mode_command(command);
+                    self.command_parser.clear_register();
+                    if command.is_change() {
+                        self.last_change = Some(change);
+                        self.recording_change = true;
+                    }
+                    keep_running
+                }
+                None => true,
+            },
+            InputMode::Insert => {
+                if let Some(command) = self.command_parser.parse_insert_mode_command(ch) {
+                    self.handle_insert_mode_command(command);
+                }
+                true
+            }
+            InputMode::Replace => {
+                if let Some(command) = self.command_parser.parse_insert_mode_command(ch) {
+                    self.handle_replace_mode_command(command);
+                }
+                true
+            }
+            InputMode::Visual | InputMode::VisualLine => {
+                match self.command_parser.parse_visual_mode_command(ch) {
+                    Some(command) => {
+                        let keep_running = self.handle_visual_mode_command(command);
+                        self.command_parser.clear_register();
+                        keep_running
+                    }
+                    None => true,
+                }
+            }
+            InputMode::Command => match self.command_parser.parse_command_mode_command(ch) {
+                Some(command) => self.handle_command_mode_command(command),
+                None => true,
+            },
+            InputMode::Search => {
+                if let Some(command) = self.command_parser.parse_command_mode_command(ch) {
+                    self.handle_search_mode_command(command);
+                }
+                true
+            }
+        };
+        if recording {
+            if let Some(change) = &mut self.last_change {
+                change.typed.push(ch);
+            }
+        }
+        if self.mode == InputMode::Normal {
+            self.recording_change = false;
+        }
+        self.commit_undo();
+        keep_running
+    }
+
+    pub fn update(&mut self, input: &mut Input) -> Result<bool, Box<dyn Error>> {
+        let Some(event) = input.next_event() else {
+            //EOF, there is nothing more to read
+            return Ok(false);
+        };
+        self.message = None;
+        if !self.handle_event(event) {
+            return Ok(false);
+        }
+        self.bottom_line = Some(self.status_line());
+        self.align_cursor();
+        self.changed = true;
+        Ok(true)
+    }
+
+    //The command being typed, a message or the mode, file and position
+    fn status_line(&self) -> Line {
+        if self.mode == InputMode::Command {
+            Line::from(format!(":{}", self.command_line.text()))
+        } else if let Some(prompt) = &self.search_prompt {
+            let prefix = if prompt.forward { '/' } else { '?' };
+            Line::from(format!("{prefix}{}", self.search_line.text()))
+        } else if let Some(message) = &self.message {
+            Line::from(message)
+        } else {
+            let mode = match self.mode {
+                InputMode::Normal => "NORMAL",
+                InputMode::Insert => "INSERT",
+                InputMode::Command => "COMMAND",
+                InputMode::Visual => "VISUAL",
+                InputMode::VisualLine => "VISUAL LINE",
+                InputMode::Search => "SEARCH",
+                InputMode::Replace => "REPLACE",
+            };
+            let status = format!(
+                " {}{}{} {}:{}",
+                self.file_manager
+                    .as_ref()
+                    .map(|fm| fm.path().display().to_string())
+                    .unwrap_or_else(|| String::from("[No Name]")),
+                match self.file_manager.as_ref().map(|fm| fm.status()) {
+                    _ if self.modified => " [+]",
+                    Some(FileManagerStatus::NewFile) => " [New]",
+                    Some(FileManagerStatus::ReadOnly) => " [RO]",
+                    _ => "",
+                },
+                self.filter
+                    .as_ref()
+                    .map(|filter| format!(
+                        " [{}/{}: {filter}]",
+                        self.view.len(),
+                        self.content.len()
+                    ))
+                    .unwrap_or_default(),
+                self.curr_line_nr() + 1,
+                self.cursor.x + 1
+            );
+            let mode = match &self.recording {
+                Some((name, _)) => format!("{mode} recording @{name}"),
+                None => mode.to_string(),
+            };
+            Line::styled(vec![Span::new(mode, Style::bold()), Span::plain(status)])
+        }
+    }
+
+    //How many screen rows the line in a row of the view takes up when wrapped
+    fn line_rows(&self, row: usize, width: usize) -> usize {
+        let line = self.format().serialize(&self.content[self.view[row]]);
+        text::wrap(&line, width).len()
+    }
+
+    //Wrapped lines take more than a row, so lines that fit by count may still not fit on the
+    //screen. Scrolls down until the cursor's row is visible.
+    fn fit_wrapped(&mut self, width: usize) {
+        loop {
+            let line = self.curr_line();
+            let (row, _) = text::wrapped_position(&line, self.cursor.x, width);
+            let rows_above: usize = (self.scroll_beg..self.curr_row())
+                .map(|row| self.line_rows(row, width))
+                .sum();
+            if rows_above + row < self.view_rows || self.cursor.y == 0 {
+                return;
+            }
+            self.scroll_beg += 1;
+            self.scroll_end += 1;
+            self.cursor.y -= 1;
+        }
+    }
+
+    pub fn render(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.changed {
+            return Ok(());
+        }
+        self.changed = false;
+
+        let (width, height) = self.backend.size().unwrap_or((80, 24));
+        let mut grid = Grid::new(width as usize, height as usize);
+        let width = (width as usize).max(1);
+        let curr_line = self.curr_line();
+        let (cursor_x, cursor_y) = if self.wrap {
+            self.scroll_x = 0;
+            self.fit_wrapped(width);
+            let (row, col) = text::wrapped_position(&curr_line, self.cursor.x, width);
+            let rows_above: usize = (self.scroll_beg..self.curr_row())
+                .map(|row| self.line_rows(row, width))
+                .sum();
+            (col, rows_above + row)
+        } else {
+            //Scroll sideways just enough to show the whole grapheme under the cursor
+            let col = text::column(&curr_line, self.cursor.x);
+            let end = text::column(&curr_line, self.cursor.x + 1).max(col + 1);
+            if col < self.scroll_x {
+                self.scroll_x = col;
+            } else if end > self.scroll_x + width {
+                self.scroll_x = end - width;
+            }
+            (col - self.scroll_x, self.cursor.y)
+        };
+
+        //While typing the matches of the new pattern are shown instead
+        let highlight = if self.mode == InputMode::Search {
+            self.typed_search()
+        } else {
+            self.search.clone().filter(|_| self.highlight)
+        };
+        let levels = tree::levels(&self.content);
+        let mut row = 0;
+        for view_row in self.scroll_beg..self.view.len() {
+            if row >= self.view_rows {
+                break;
+            }
+            let idx = self.view[view_row];
+            let c = &self.content[idx];
+            let line = self.format().serialize(c);
+            let spans = match self.selected_span(view_row) {
+                //The selection is shown without the colors
+                Some((from, to)) => vec![
+                    Span::plain(text::slice(&line, 0, from)),
+                    Span::new(text::slice(&line, from, to), Style::invert()),
+                    Span::plain(text::slice(&line, to, usize::MAX)),
+                ],
+                None => c.render(self.format()),
+            };
+            let spans = match &highlight {
+                Some(regex) => {
+                    search::matches(regex, &line)
+                        .into_iter()
+                        .fold(spans, |spans, (from, to)| {
+                            let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+                            restyle_spans(&spans, from, to, style)
+                        })
+                }
+                None => spans,
+            };
+            //Parents show how far along their subtasks are, and how much is folded
+            let mut spans = spans;
+            let (done, total) = tree::progress(&self.content, &levels, idx);
+            if total > 0 {
+                spans.push(Span::new(format!(" [{done}/{total}]"), Style::faint()));
+            }
+            let hidden = tree::subtree_end(&levels, idx) - idx - 1;
+            if hidden > 0 && self.folds.binary_search(&idx).is_ok() {
+                spans.push(Span::new(format!(" +{hidden} folded"), Style::faint()));
+            }
+            if self.wrap {
+                let starts = text::wrap(&line, width);
+                let ends = starts.iter().skip(1).copied().chain([usize::MAX]);
+                for (from, to) in starts.iter().zip(ends) {
+                    if row >= self.view_rows {
+                        break;
+                    }
+                    grid.set_line(row, &slice_spans(&spans, *from, to));
+                    row += 1;
+                }
+            } else {
+                //A wide grapheme cut by the left edge is left out, with spaces in its place
+                let mut first = text::at_column(&line, self.scroll_x);
+                if text::column(&line, first) < self.scroll_x {
+                    first += 1;
+                }
+                let pad = text::column(&line, first).saturating_sub(self.scroll_x);
+                let mut visible = vec![Span::plain(" ".repeat(pad))];
+                visible.extend(slice_spans(&spans, first, usize::MAX));
+                grid.set_line(row, &visible);
+                row += 1;
+            }
+        }
+        if let Some(bl) = &self.bottom_line {
+            grid.set_line(self.view_rows, &bl.spans);
+        }
+        let changed = match self.frame.take() {
+            Some(frame) if frame.width() == grid.width() && frame.height() == grid.height() => {
+                grid.diff(&frame)
+            }
+            //Nothing to compare to, start over from an empty screen
+            _ => {
+                self.backend.clear()?;
+                grid.diff(&Grid::new(grid.width(), grid.height()))
+            }
+        };
+        let cells: Vec<_> = changed
+            .into_iter()
+            .map(|(x, y, cell)| (x as u16, y as u16, cell))
+            .collect();
+        self.backend.draw(&cells)?;
+
+        //The cursor is placed by column, which isn't the position with wide characters
+        let prompt = match self.mode {
+            InputMode::Command => Some(&self.command_line),
+            InputMode::Search => Some(&self.search_line),
+            _ => None,
+        };
+        let (x, y) = if let Some(prompt) = prompt {
+            let line = prompt.text();
+            //After the ':', '/' or '?'
+            let x = text::width(&line[..prompt.cursor()]) + 1;
+            (x, self.view_rows)
+        } else {
+            (cursor_x, cursor_y)
+        };
+        self.backend
+            .set_cursor(x as u16, y as u16, Cursor::shape(&self.mode))?;
+        self.backend.flush()?;
+        self.frame = Some(grid);
+        Ok(())
+    }
+}
+
+mod test {
+
+    #[cfg(test)]
+    use super::MinTodo;
+    #[cfg(test)]
+    use crate::{
+        backend::{CursorShape, MemoryBackend},
+        char_parser::{Decoder, Event},
+        command_line::{ExCommand, Setting},
+        command_parser::{CommandModeCommand, InsertModeCommand, NormalModeCommand},
+        date::Date,
+        file_manager::{FileManager, Marks},
+        min_todo::InputMode,
+        registers::Register,
+        screen::Color,
+        text,
+        todo_item::TodoItem,
+    };
+
+    #[cfg(test)]
+    fn headless() -> MinTodo {
+        MinTodo::new(Box::new(MemoryBackend::new(80, 24)))
+    }
+
+    #[test]
+    fn movement_nomal_mode() {
+        let mut mt = headless();
+        mt.add_line(TodoItem::from("Line of length 17"));
+        mt.add_line(TodoItem::from("Line of length 17"));
+
+        mt.handle_normal_mode_command(NormalModeCommand::MoveDown);
+        assert_eq!(mt.curr_line_nr(), 1);
+
+        mt.handle_normal_mode_command(NormalModeCommand::MoveUp);
+        assert_eq!(mt.curr_line_nr(), 0);
+
+        mt.handle_normal_mode_command(NormalModeCommand::MoveRight);
+        assert_eq!(mt.cursor.x, 1);
+
+        mt.handle_normal_mode_command(NormalModeCommand::MoveLeft);
+        assert_eq!(mt.cursor.x, 0);
+
+        mt.handle_normal_mode_command(NormalModeCommand::MoveLeft);
+        assert_eq!(mt.cursor.x, 0);
+
+        //A count past the start of the line stops there
+        keys(&mut mt, b"ll5h");
+        assert_eq!(mt.cursor.x, 0);
+        keys(&mut mt, b"3l99999999999h");
+        assert_eq!(mt.cursor.x, 0);
+
+        mt.handle_normal_mode_command(NormalModeCommand::ToEnd);
+        assert_eq!(mt.cursor.x, 17);
+
+        mt.handle_normal_mode_command(NormalModeCommand::ToBeg);
         assert_eq!(mt.cursor.x, 0);
 
         mt.handle_normal_mode_command(NormalModeCommand::NextWord);
@@ -495,9 +2488,9 @@ mod test {
 
     #[test]
     fn movement_insert_mode() {
-        let mut mt = MinTodo::new();
-        mt.add_line(Line::from("Line of length 17"));
-        mt.add_line(Line::from("Line of length 17"));
+        let mut mt = headless();
+        mt.add_line(TodoItem::from("Line of length 17"));
+        mt.add_line(TodoItem::from("Line of length 17"));
 
         mt.handle_insert_mode_command(InsertModeCommand::MoveDown);
         assert_eq!(mt.curr_line_nr(), 1);
@@ -511,4 +2504,1326 @@ mod test {
         mt.handle_insert_mode_command(InsertModeCommand::MoveLeft);
         assert_eq!(mt.cursor.x, 0);
     }
+
+    #[test]
+    fn unicode() {
+        let mut mt = headless();
+        mt.add_line(TodoItem::from("日本 cafe\u{301} ok"));
+
+        //Positions are graphemes, the columns count wide characters twice
+        keys(&mut mt, b"l");
+        assert_eq!(mt.cursor.x, 1);
+        assert_eq!(text::column(&mt.curr_line(), mt.cursor.x), 2);
+        keys(&mut mt, b"w");
+        assert_eq!(mt.cursor.x, 3);
+        assert_eq!(text::column(&mt.curr_line(), mt.cursor.x), 5);
+        keys(&mut mt, b"w");
+        assert_eq!(mt.cursor.x, 8);
+        keys(&mut mt, b"b");
+        assert_eq!(mt.cursor.x, 3);
+        keys(&mut mt, b"$");
+        assert_eq!(mt.cursor.x, 9);
+
+        keys(&mut mt, b"0x");
+        assert_eq!(lines(&mt), ["本 cafe\u{301} ok"]);
+        assert_eq!(
+            mt.registers.get(None),
+            Some(&Register::Charwise("日".into()))
+        );
+
+        //A combining accent typed after a letter becomes part of it
+        keys(&mut mt, "ie\u{301}".as_bytes());
+        assert_eq!(lines(&mt), ["e\u{301}本 cafe\u{301} ok"]);
+        assert_eq!(mt.cursor.x, 1);
+        keys(&mut mt, b"\x7f");
+        assert_eq!(lines(&mt), ["本 cafe\u{301} ok"]);
+        assert_eq!(mt.cursor.x, 0);
+
+        keys(&mut mt, b"\x1b");
+        keys(&mut mt, b"wdw");
+        assert_eq!(lines(&mt), ["本 ok"]);
+        keys(&mut mt, b"0p");
+        assert_eq!(lines(&mt), ["本cafe\u{301}  ok"]);
+        assert_eq!(mt.cursor.x, 5);
+    }
+
+    #[test]
+    fn screen() {
+        let backend = MemoryBackend::new(20, 10);
+        let mut mt = MinTodo::new(Box::new(backend.clone()));
+        mt.add_line(TodoItem::from("(A) 日本 +proj"));
+        mt.add_line(TodoItem::from("x done"));
+        mt.render().unwrap();
+        assert_eq!(backend.rows()[..3], ["(A) 日本 +proj", "x done", ""]);
+        assert!(backend.cell(0, 0).style.bold);
+        assert!(!backend.cell(4, 0).style.bold);
+        assert_eq!(backend.cell(9, 0).style.fg, Color::Blue);
+        assert!(backend.cell(0, 1).style.faint);
+        assert_eq!(backend.cursor(), (0, 0, CursorShape::Block));
+
+        //The cursor is on the column, after the wide 日
+        keys(&mut mt, b"llllli");
+        mt.render().unwrap();
+        assert_eq!(backend.cursor(), (6, 0, CursorShape::Bar));
+
+        keys(&mut mt, b"\x1b");
+        keys(&mut mt, b"vl");
+        mt.render().unwrap();
+        assert!((4..8).all(|x| backend.cell(x, 0).style.invert));
+        assert!(!backend.cell(8, 0).style.invert);
+        assert_eq!(backend.rows()[0], "(A) 日本 +proj");
+    }
+
+    #[test]
+    fn screen_diff() {
+        let backend = MemoryBackend::new(20, 10);
+        let mut mt = MinTodo::new(Box::new(backend.clone()));
+        mt.add_line(TodoItem::from("first"));
+        mt.add_line(TodoItem::from("second"));
+        mt.render().unwrap();
+        //The first frame clears the screen and draws all the text
+        assert_eq!(backend.drawn(), "firstsecond".len());
+
+        //Unchanged frames don't draw anything
+        keys(&mut mt, b"l");
+        mt.render().unwrap();
+        assert_eq!(backend.drawn(), 0);
+
+        //Deleting the first char shifts the rest of the line
+        keys(&mut mt, b"0x");
+        mt.render().unwrap();
+        assert_eq!(backend.drawn(), "first".len());
+        assert_eq!(backend.rows()[..2], ["irst", "second"]);
+
+        keys(&mut mt, b"jx");
+        mt.render().unwrap();
+        assert_eq!(backend.drawn(), "second".len());
+        assert_eq!(backend.rows()[..2], ["irst", "econd"]);
+    }
+
+    #[test]
+    fn resize() {
+        let backend = MemoryBackend::new(20, 24);
+        let mut mt = MinTodo::new(Box::new(backend.clone()));
+        for i in 0..40 {
+            mt.add_line(TodoItem::from(i.to_string().as_str()));
+        }
+        mt.goto_row(30);
+        assert_eq!(mt.cursor.y, 18);
+
+        //Smaller than the scroll margins, the current line stays on screen
+        backend.resize(20, 4);
+        mt.handle_event(Event::Resize);
+        assert_eq!(
+            (mt.view_rows, mt.start_scroll_up, mt.start_scroll_down),
+            (3, 1, 2)
+        );
+        assert_eq!(mt.curr_line_nr(), 30);
+        assert_eq!((mt.scroll_beg, mt.cursor.y), (28, 2));
+        mt.render().unwrap();
+        assert_eq!(backend.rows()[..3], ["28", "29", "30"]);
+        assert_eq!(backend.cursor(), (0, 2, CursorShape::Block));
+
+        keys(&mut mt, b"jj");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.y), (32, 2));
+        keys(&mut mt, b"kkkk");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.y), (28, 1));
+        keys(&mut mt, b"G");
+        mt.render().unwrap();
+        assert_eq!(backend.rows()[..3], ["37", "38", "39"]);
+
+        //A single row, there's no room for the bottom line
+        backend.resize(20, 1);
+        mt.handle_event(Event::Resize);
+        assert_eq!((mt.view_rows, mt.cursor.y), (1, 0));
+        keys(&mut mt, b"kk");
+        assert_eq!((mt.curr_line_nr(), mt.scroll_beg), (37, 37));
+        mt.render().unwrap();
+        assert_eq!(backend.rows(), ["37"]);
+
+        //Growing again shows as many lines as fit
+        backend.resize(20, 24);
+        mt.handle_event(Event::Resize);
+        assert_eq!((mt.scroll_beg, mt.cursor.y), (17, 20));
+        mt.render().unwrap();
+        assert_eq!(backend.rows()[0], "17");
+        assert_eq!(backend.rows()[22], "39");
+        keys(&mut mt, b"gg");
+        assert_eq!((mt.scroll_beg, mt.cursor.y), (0, 0));
+    }
+
+    #[test]
+    fn counted_goto() {
+        let backend = MemoryBackend::new(20, 24);
+        let mut mt = MinTodo::new(Box::new(backend.clone()));
+        for i in 0..40 {
+            mt.add_line(TodoItem::from(i.to_string().as_str()));
+        }
+        //Within the scroll margins like any other jump
+        keys(&mut mt, b"30G");
+        assert_eq!(
+            (mt.curr_line_nr(), mt.scroll_beg, mt.cursor.y),
+            (29, 11, 18)
+        );
+        keys(&mut mt, b"5gg");
+        assert_eq!((mt.curr_line_nr(), mt.scroll_beg, mt.cursor.y), (4, 0, 4));
+        //Past the end it's the last line, without scrolling past the content
+        keys(&mut mt, b"99G");
+        mt.render().unwrap();
+        assert_eq!(mt.curr_line_nr(), 39);
+        assert_eq!(backend.rows()[0], "17");
+        assert_eq!(backend.rows()[22], "39");
+
+        let mut mt = headless();
+        for line in ["parent", "  child", "other"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b"5G");
+        assert_eq!((mt.curr_line_nr(), mt.scroll_beg, mt.cursor.y), (2, 0, 2));
+        //The count is a row of what's shown
+        keys(&mut mt, b"ggzc2G");
+        assert_eq!((mt.curr_line_nr(), mt.scroll_beg, mt.cursor.y), (2, 0, 1));
+    }
+
+    #[test]
+    fn long_lines() {
+        let backend = MemoryBackend::new(10, 6);
+        let mut mt = MinTodo::new(Box::new(backend.clone()));
+        mt.add_line(TodoItem::from("0123456789abcdef"));
+        mt.add_line(TodoItem::from("short"));
+        mt.add_line(TodoItem::from("x日本語日本語!"));
+        mt.render().unwrap();
+        assert_eq!(backend.rows()[..3], ["0123456789", "short", "x日本語日"]);
+
+        //Every line scrolls sideways to show the cursor
+        keys(&mut mt, b"$");
+        mt.render().unwrap();
+        assert_eq!(backend.rows()[..3], ["6789abcdef", "", " 日本語!"]);
+        assert_eq!(backend.cursor(), (9, 0, CursorShape::Block));
+        keys(&mut mt, b"0");
+        mt.render().unwrap();
+        keys(&mut mt, b"jj$");
+        mt.render().unwrap();
+        //本 is cut by the left edge
+        assert_eq!(backend.rows()[..3], ["456789abcd", "t", " 語日本語!"]);
+        assert_eq!(backend.cursor(), (9, 2, CursorShape::Block));
+        keys(&mut mt, b"0");
+        mt.render().unwrap();
+        assert_eq!(backend.rows()[0], "0123456789");
+
+        mt.handle_ex_command(ExCommand::Set(Setting::Wrap(true)));
+        keys(&mut mt, b"$");
+        mt.render().unwrap();
+        assert_eq!(
+            backend.rows()[..5],
+            ["0123456789", "abcdef", "short", "x日本語日", "本語!"]
+        );
+        assert_eq!(backend.cursor(), (4, 4, CursorShape::Block));
+
+        //j and k go over the rows of a wrapped line
+        keys(&mut mt, b"gg0j");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (0, 10));
+        keys(&mut mt, b"j");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (1, 0));
+        keys(&mut mt, b"k");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (0, 10));
+        keys(&mut mt, b"k$j");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (1, 4));
+        keys(&mut mt, b"jj");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (2, 6));
+
+        //Scrolls far enough for the cursor's row, even though the lines would fit by count
+        mt.add_line(TodoItem::from("3"));
+        mt.add_line(TodoItem::from("4"));
+        keys(&mut mt, b"G");
+        mt.render().unwrap();
+        assert_eq!(
+            backend.rows()[..5],
+            ["short", "x日本語日", "本語!", "3", "4"]
+        );
+        assert_eq!(backend.cursor(), (0, 4, CursorShape::Block));
+    }
+
+    #[test]
+    fn quit_with_unsaved_changes() {
+        let path = std::env::temp_dir().join(format!("min_todo_{}_quit", std::process::id()));
+        std::fs::write(&path, "first\n").unwrap();
+        let mut mt = MinTodo::open(
+            FileManager::new(&path).unwrap(),
+            Box::new(MemoryBackend::new(80, 24)),
+        )
+        .unwrap();
+
+        mt.handle_insert_mode_command(InsertModeCommand::Insert('a'));
+        assert!(mt.handle_ex_command(ExCommand::Quit { force: false }));
+        assert!(mt.message.is_some());
+        assert!(mt.handle_ex_command(ExCommand::Write {
+            path: None,
+            force: false
+        }));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "afirst\n");
+        assert!(!mt.handle_ex_command(ExCommand::Quit { force: false }));
+
+        mt.handle_insert_mode_command(InsertModeCommand::Insert('b'));
+        assert!(!mt.handle_ex_command(ExCommand::Quit { force: true }));
+        std::fs::remove_file(&path).unwrap();
+
+        //Without a file the changes are kept all the same
+        let mut mt = headless();
+        mt.add_line(TodoItem::from("first"));
+        keys(&mut mt, b"x");
+        assert!(mt.handle_ex_command(ExCommand::Quit { force: false }));
+        assert_eq!(
+            mt.message.as_deref(),
+            Some("No write since last change (add ! to override)")
+        );
+        keys(&mut mt, b"u");
+        //Ctrl-C doesn't quit even without changes
+        assert!(mt.handle_normal_mode_command(NormalModeCommand::Interrupt));
+        assert_eq!(
+            mt.message.as_deref(),
+            Some("Type :q and press <Enter> to exit")
+        );
+        assert!(!mt.handle_ex_command(ExCommand::Quit { force: false }));
+    }
+
+    #[test]
+    fn status_line() {
+        let status = |mt: &MinTodo| {
+            let line = mt.status_line();
+            line.spans
+                .iter()
+                .map(|span| span.text.as_str())
+                .collect::<String>()
+        };
+        let mut mt = headless();
+        mt.add_line(TodoItem::from("first"));
+        mt.add_line(TodoItem::from("second"));
+        assert_eq!(status(&mt), "NORMAL [No Name] 1:1");
+        keys(&mut mt, b"jlx");
+        assert_eq!(status(&mt), "NORMAL [No Name] [+] 2:2");
+        keys(&mut mt, b"qai");
+        assert_eq!(status(&mt), "INSERT recording @a [No Name] [+] 2:2");
+    }
+
+    #[test]
+    fn write_other_file() {
+        let dir = std::env::temp_dir().join(format!("min_todo_{}_other", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("todo.txt"), "first\n").unwrap();
+        std::fs::write(dir.join("other.txt"), "other\n").unwrap();
+        let mut mt = MinTodo::open(
+            FileManager::new(dir.join("todo.txt")).unwrap(),
+            Box::new(MemoryBackend::new(80, 24)),
+        )
+        .unwrap();
+        let path = |mt: &MinTodo| mt.file_manager.as_ref().unwrap().path().to_path_buf();
+
+        //An existing file is only overwritten with !
+        keys(
+            &mut mt,
+            format!(":w {}\r", dir.join("other.txt").display()).as_bytes(),
+        );
+        assert_eq!(
+            mt.message.as_deref(),
+            Some("File exists (add ! to override)")
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("other.txt")).unwrap(),
+            "other\n"
+        );
+        keys(
+            &mut mt,
+            format!(":w! {}\r", dir.join("other.txt").display()).as_bytes(),
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("other.txt")).unwrap(),
+            "first\n"
+        );
+        assert_eq!(path(&mt), dir.join("todo.txt"));
+
+        keys(
+            &mut mt,
+            format!(":sav {}\r", dir.join("other.txt").display()).as_bytes(),
+        );
+        assert_eq!(
+            mt.message.as_deref(),
+            Some("File exists (add ! to override)")
+        );
+        assert_eq!(path(&mt), dir.join("todo.txt"));
+        //A file that can't be written leaves the buffer where it was
+        keys(
+            &mut mt,
+            format!(":sav {}\r", dir.join("none/new.txt").display()).as_bytes(),
+        );
+        assert!(!dir.join("none").exists());
+        assert_eq!(path(&mt), dir.join("todo.txt"));
+        keys(
+            &mut mt,
+            format!("x:sav! {}\r", dir.join("other.txt").display()).as_bytes(),
+        );
+        assert_eq!(path(&mt), dir.join("other.txt"));
+        assert!(!mt.is_modified());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("other.txt")).unwrap(),
+            "irst\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn todo_commands() {
+        let mut mt = headless();
+        mt.add_line(TodoItem::from("(B) first"));
+        mt.add_line(TodoItem::from("second"));
+        mt.add_line(TodoItem::from("third"));
+
+        mt.command_parser.set_nr_prefix(Some(2));
+        mt.handle_normal_mode_command(NormalModeCommand::ToggleDone);
+        assert!(mt.content[0].done && mt.content[1].done && !mt.content[2].done);
+        assert_eq!(mt.content[0].meta("pri"), Some("B"));
+        mt.handle_normal_mode_command(NormalModeCommand::ToggleDone);
+        assert_eq!(mt.content[0].to_string(), "(B) first");
+
+        mt.command_parser.set_nr_prefix(Some(2));
+        mt.handle_normal_mode_command(NormalModeCommand::LowerPriority);
+        assert_eq!(mt.content[0].priority, Some('D'));
+        mt.handle_normal_mode_command(NormalModeCommand::RaisePriority);
+        assert_eq!(mt.content[0].priority, Some('C'));
+
+        mt.command_parser.set_nr_prefix(Some(3));
+        mt.handle_normal_mode_command(NormalModeCommand::SetDueDate);
+        let due = Date::today().add_days(3).unwrap().to_string();
+        assert_eq!(mt.content[0].meta("due"), Some(due.as_str()));
+
+        mt.command_parser.set_nr_prefix(Some(3));
+        mt.handle_normal_mode_command(NormalModeCommand::AddTag);
+        assert_eq!(mt.mode, InputMode::Command);
+        assert_eq!(mt.command_line.text(), "tag ");
+        mt.command_line.set_text("tag +project");
+        mt.handle_command_mode_command(CommandModeCommand::Execute);
+        assert!(mt.content.iter().all(|item| item.has_word("+project")));
+    }
+
+    #[test]
+    fn archive() {
+        let dir = std::env::temp_dir().join(format!("min_todo_{}_archive", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("todo.txt"), "x done\n  x sub\nopen\n").unwrap();
+        let mut mt = MinTodo::open(
+            FileManager::new(dir.join("todo.txt")).unwrap(),
+            Box::new(MemoryBackend::new(80, 24)),
+        )
+        .unwrap();
+
+        //A folded item takes its subtasks along
+        keys(&mut mt, b"zcga");
+        assert_eq!(lines(&mt), ["open"]);
+        assert!(mt.is_modified());
+        //Nothing is written until the file is, so undo takes it all back
+        assert!(!dir.join("done.txt").exists());
+        keys(&mut mt, b"u");
+        assert_eq!(mt.archived, []);
+        keys(&mut mt, b"zcga:w\r");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("done.txt")).unwrap(),
+            "x done\n  x sub\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+            "open\n"
+        );
+        assert!(!mt.is_modified());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(test)]
+    fn keys(mt: &mut MinTodo, keys: &[u8]) {
+        let mut decoder = Decoder::new();
+        decoder.feed(keys);
+        let mut events = Vec::new();
+        while let Some(event) = decoder.next_event() {
+            events.push(event);
+        }
+        events.extend(decoder.flush());
+        for event in events {
+            mt.handle_event(event);
+            mt.align_cursor();
+            mt.changed = true;
+        }
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut mt = headless();
+        mt.add_line(TodoItem::from("first"));
+        mt.add_line(TodoItem::from("second"));
+        mt.add_line(TodoItem::from("third"));
+
+        //A whole insert session is a single step
+        keys(&mut mt, b"jlliab\x1b");
+        assert_eq!(mt.content[1].to_string(), "seabcond");
+        //And so is a counted command
+        keys(&mut mt, b"02x");
+        assert_eq!(mt.content[1].to_string(), "abcond");
+        keys(&mut mt, b"kdd");
+        assert_eq!(mt.content.len(), 2);
+
+        //The cursor goes back to where the change started
+        keys(&mut mt, b"u");
+        assert_eq!(mt.content.len(), 3);
+        assert_eq!(mt.curr_line_nr(), 0);
+        keys(&mut mt, b"u");
+        assert_eq!(mt.content[1].to_string(), "seabcond");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (1, 0));
+        keys(&mut mt, b"u");
+        assert_eq!(mt.content[1].to_string(), "second");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (1, 2));
+        keys(&mut mt, b"u");
+        assert_eq!(mt.message.as_deref(), Some("Already at oldest change"));
+
+        //Ctrl-R
+        keys(&mut mt, b"\x12\x12");
+        assert_eq!(mt.content[1].to_string(), "abcond");
+        keys(&mut mt, b"2\x12");
+        assert_eq!(mt.content.len(), 2);
+        assert_eq!(mt.message.as_deref(), Some("Already at newest change"));
+        keys(&mut mt, b"3u");
+        assert_eq!(mt.content[1].to_string(), "second");
+    }
+
+    #[test]
+    fn undo_to_saved() {
+        let path = std::env::temp_dir().join(format!("min_todo_{}_undo", std::process::id()));
+        std::fs::write(&path, "first\n").unwrap();
+        let mut mt = MinTodo::open(
+            FileManager::new(&path).unwrap(),
+            Box::new(MemoryBackend::new(80, 24)),
+        )
+        .unwrap();
+
+        keys(&mut mt, b"x");
+        assert!(mt.is_modified());
+        keys(&mut mt, b"u");
+        assert!(!mt.is_modified());
+        keys(&mut mt, b"\x12");
+        assert!(mt.is_modified());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn yank_put() {
+        let mut mt = headless();
+        mt.add_line(TodoItem::from("first"));
+        mt.add_line(TodoItem::from("second"));
+        mt.add_line(TodoItem::from("third"));
+
+        //Linewise with a count
+        keys(&mut mt, b"yy2p");
+        assert_eq!(
+            mt.content.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+            ["first", "first", "first", "second", "third"]
+        );
+        assert_eq!(mt.curr_line_nr(), 1);
+        keys(&mut mt, b"3ddGP");
+        assert_eq!(
+            mt.content.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+            ["first", "first", "first", "second", "third"]
+        );
+        assert_eq!(mt.curr_line_nr(), 1);
+
+        //Characterwise
+        keys(&mut mt, b"gg2x$p");
+        assert_eq!(mt.content[0].to_string(), "rstfi");
+        assert_eq!(mt.cursor.x, 4);
+        keys(&mut mt, b"02P");
+        assert_eq!(mt.content[0].to_string(), "fifirstfi");
+
+        //Named registers, uppercase appends
+        keys(&mut mt, b"j\"ayyj\"Ayyj\"bdd\"ap");
+        assert_eq!(
+            mt.content.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+            ["fifirstfi", "first", "first", "third", "first", "first"]
+        );
+        assert_eq!(mt.curr_line_nr(), 4);
+        //A named delete doesn't go to the delete ring
+        keys(&mut mt, b"\"1P");
+        assert_eq!(mt.content[6].to_string(), "second");
+        keys(&mut mt, b"\"cp");
+        assert_eq!(mt.message.as_deref(), Some("Nothing in register c"));
+    }
+
+    #[cfg(test)]
+    fn lines(mt: &MinTodo) -> Vec<String> {
+        mt.content.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn search() {
+        let mut mt = headless();
+        for line in [
+            "Call mom",
+            "buy milk +home",
+            "pay rent",
+            "call Bob about milk",
+        ] {
+            mt.add_line(TodoItem::from(line));
+        }
+        let position = |mt: &MinTodo| (mt.curr_line_nr(), mt.cursor.x);
+
+        //The cursor follows what's typed, Esc goes back
+        keys(&mut mt, b"/mi");
+        assert_eq!(mt.mode, InputMode::Search);
+        assert_eq!(position(&mt), (1, 4));
+        keys(&mut mt, b"\x1b");
+        assert_eq!(mt.mode, InputMode::Normal);
+        assert_eq!(position(&mt), (0, 0));
+
+        keys(&mut mt, b"/milk\r");
+        assert_eq!(position(&mt), (1, 4));
+        keys(&mut mt, b"n");
+        assert_eq!(position(&mt), (3, 15));
+        keys(&mut mt, b"n");
+        assert_eq!(position(&mt), (1, 4));
+        assert_eq!(
+            mt.message.take().as_deref(),
+            Some("search hit BOTTOM, continuing at TOP")
+        );
+        keys(&mut mt, b"N");
+        assert_eq!(position(&mt), (3, 15));
+
+        //Smart-case, and n keeps going backwards after ?
+        keys(&mut mt, b"?call\r");
+        assert_eq!(position(&mt), (3, 0));
+        keys(&mut mt, b"n");
+        assert_eq!(position(&mt), (0, 0));
+        keys(&mut mt, b"N");
+        assert_eq!(position(&mt), (3, 0));
+        keys(&mut mt, b"/Call\r");
+        assert_eq!(position(&mt), (0, 0));
+        keys(&mut mt, b"2/milk\r");
+        assert_eq!(position(&mt), (3, 15));
+
+        mt.message = None;
+        keys(&mut mt, b"gg0/xyz\r");
+        assert_eq!(position(&mt), (0, 0));
+        assert_eq!(mt.message.take().as_deref(), Some("Pattern not found: xyz"));
+        keys(&mut mt, b"/(\r");
+        assert_eq!(mt.message.take().as_deref(), Some("Invalid pattern: ("));
+        //History, the invalid pattern is in it too
+        keys(&mut mt, b"/\x1b[A\x1b[A");
+        assert_eq!(mt.search_line.text(), "xyz");
+        keys(&mut mt, b"\x1b");
+
+        //As a motion, within the line or over whole lines
+        keys(&mut mt, b"d/mom\r");
+        assert_eq!(mt.content[0].to_string(), "mom");
+        keys(&mut mt, b"u");
+        keys(&mut mt, b"d/rent\r");
+        assert_eq!(lines(&mt), ["call Bob about milk"]);
+        keys(&mut mt, b"u");
+        assert_eq!(mt.content.len(), 4);
+        keys(&mut mt, b"\"ay/mom\r");
+        assert_eq!(
+            mt.registers.get(Some('a')),
+            Some(&Register::Charwise("Call ".into()))
+        );
+    }
+
+    #[test]
+    fn search_highlight() {
+        let backend = MemoryBackend::new(20, 5);
+        let mut mt = MinTodo::new(Box::new(backend.clone()));
+        mt.add_line(TodoItem::from("buy milk"));
+        mt.add_line(TodoItem::from("milk again"));
+        let highlighted = |x, y| backend.cell(x, y).style.bg == Color::Yellow;
+
+        keys(&mut mt, b"/mil");
+        mt.render().unwrap();
+        assert!(highlighted(4, 0) && highlighted(6, 0) && !highlighted(7, 0));
+        assert!(highlighted(0, 1));
+        keys(&mut mt, b"k\r");
+        mt.render().unwrap();
+        assert!(highlighted(7, 0) && !highlighted(3, 0));
+        keys(&mut mt, b":noh\r");
+        mt.render().unwrap();
+        assert!(!highlighted(4, 0) && !highlighted(0, 1));
+        //n shows them again
+        keys(&mut mt, b"n");
+        mt.render().unwrap();
+        assert!(highlighted(4, 0));
+    }
+
+    #[test]
+    fn filter() {
+        let backend = MemoryBackend::new(40, 6);
+        let mut mt = MinTodo::new(Box::new(backend.clone()));
+        for line in [
+            "(A) Fix login +backend",
+            "Buy milk",
+            "Write docs +backend",
+            "Call mom",
+            "x Deploy +backend",
+        ] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b"jjj:filter is:open +backend\r");
+        mt.render().unwrap();
+        assert_eq!(
+            backend.rows()[..3],
+            ["(A) Fix login +backend", "Write docs +backend", ""]
+        );
+        //Call mom isn't shown, the cursor went to the next line that is
+        assert_eq!((mt.curr_row(), mt.curr_line_nr()), (1, 2));
+
+        //Edits go to the line shown, it stays even when it no longer matches
+        keys(&mut mt, b"t");
+        assert!(mt.content[2].done);
+        assert_eq!(mt.view, [0, 2]);
+        keys(&mut mt, b"ggoinew +backend\x1b");
+        assert_eq!(mt.view, [0, 1, 3]);
+        assert_eq!(mt.content[1].to_string(), "new +backend");
+        //Undo applies the filter again, the line that was done is back
+        keys(&mut mt, b"uuu");
+        assert_eq!(mt.view, [0, 2]);
+        assert!(!mt.content[2].done);
+
+        //The lines in between aren't touched
+        keys(&mut mt, b"ggdj");
+        assert_eq!(lines(&mt), ["Buy milk", "Call mom", "x Deploy +backend"]);
+        //Nothing that matches is left, everything is shown again
+        assert_eq!(mt.view, [0, 1, 2]);
+        assert_eq!(
+            mt.message.take().as_deref(),
+            Some("No items match the filter: is:open +backend")
+        );
+        keys(&mut mt, b"u:filter +backend\r");
+        assert_eq!(mt.view, [0, 2, 4]);
+        keys(&mut mt, b"jdGp");
+        assert_eq!(
+            lines(&mt),
+            [
+                "(A) Fix login +backend",
+                "Write docs +backend",
+                "x Deploy +backend",
+                "Buy milk",
+                "Call mom"
+            ]
+        );
+        keys(&mut mt, b"uu");
+
+        keys(&mut mt, b"G:filter\r");
+        assert_eq!(mt.view.len(), 5);
+        assert_eq!(mt.curr_line_nr(), 4);
+        keys(&mut mt, b":filter pri:Z\r");
+        assert_eq!(mt.view.len(), 5);
+        assert_eq!(
+            mt.message.take().as_deref(),
+            Some("No items match the filter: pri:Z")
+        );
+        keys(&mut mt, b":filter pri:z\r");
+        assert_eq!(
+            mt.message.take().as_deref(),
+            Some("Invalid argument: pri:z")
+        );
+    }
+
+    #[test]
+    fn sort() {
+        let mut mt = headless();
+        for line in ["(B) b", "(A) a", "c", "(C) d"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b":sort pri\r");
+        assert_eq!(lines(&mt), ["(A) a", "(B) b", "(C) d", "c"]);
+        //A single undo step
+        keys(&mut mt, b"u");
+        assert_eq!(lines(&mt), ["(B) b", "(A) a", "c", "(C) d"]);
+
+        //Only the rows the count covers
+        keys(&mut mt, b"j2:sort!\r");
+        assert_eq!(lines(&mt), ["(B) b", "c", "(A) a", "(C) d"]);
+        keys(&mut mt, b"u");
+        keys(&mut mt, b"ggVjj:sort text\r");
+        assert_eq!(lines(&mt), ["(A) a", "(B) b", "c", "(C) d"]);
+        assert_eq!(mt.mode, InputMode::Normal);
+
+        keys(&mut mt, b"gs");
+        assert_eq!(mt.mode, InputMode::Command);
+        assert_eq!(mt.command_line.text(), "sort ");
+        assert_eq!(mt.ex_count, None);
+        keys(&mut mt, b"-pri\r");
+        assert_eq!(lines(&mt), ["c", "(C) d", "(B) b", "(A) a"]);
+
+        //Subtasks stay under their parent and are sorted there
+        let mut mt = headless();
+        for line in [
+            "(B) parent",
+            "  (B) child",
+            "  (A) child",
+            "(C) other",
+            "(A) first",
+        ] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b"Gma:sort\r");
+        assert_eq!(
+            lines(&mt),
+            [
+                "(A) first",
+                "(B) parent",
+                "  (A) child",
+                "  (B) child",
+                "(C) other"
+            ]
+        );
+        assert_eq!(mt.marks.get(&'a'), Some(&(0, 0)));
+        //Only the children when that's all that's selected
+        keys(&mut mt, b"uggjj2:sort!\r");
+        assert_eq!(
+            lines(&mt),
+            [
+                "(B) parent",
+                "  (B) child",
+                "  (A) child",
+                "(C) other",
+                "(A) first"
+            ]
+        );
+        keys(&mut mt, b"ggj2:sort\r");
+        assert_eq!(lines(&mt)[1..3], ["  (A) child", "  (B) child"]);
+    }
+
+    #[test]
+    fn subtasks() {
+        let backend = MemoryBackend::new(40, 8);
+        let mut mt = MinTodo::new(Box::new(backend.clone()));
+        for line in ["Release", "x Write notes", "Tag", "Push", "Other"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b"j3>>jj>>");
+        assert_eq!(
+            lines(&mt),
+            ["Release", "  x Write notes", "  Tag", "    Push", "Other"]
+        );
+        mt.render().unwrap();
+        assert_eq!(
+            backend.rows()[..5],
+            [
+                "Release [1/3]",
+                "  x Write notes",
+                "  Tag [0/1]",
+                "    Push",
+                "Other"
+            ]
+        );
+
+        //Folding the subtree the cursor is in, the cursor goes to the parent
+        keys(&mut mt, b"zc");
+        assert_eq!(mt.view, [0, 1, 2, 4]);
+        assert_eq!(mt.curr_line_nr(), 2);
+        keys(&mut mt, b"kzc");
+        assert_eq!(mt.view, [0, 4]);
+        assert_eq!(mt.curr_line_nr(), 0);
+        mt.render().unwrap();
+        assert_eq!(
+            backend.rows()[..3],
+            ["Release [1/3] +3 folded", "Other", ""]
+        );
+        keys(&mut mt, b"za");
+        assert_eq!(mt.view, [0, 1, 2, 4]);
+        keys(&mut mt, b"zR");
+        assert_eq!(mt.view, [0, 1, 2, 3, 4]);
+        keys(&mut mt, b"zM");
+        assert_eq!(mt.view, [0, 4]);
+
+        //The subtree goes along with its parent
+        keys(&mut mt, b">>");
+        assert_eq!(
+            lines(&mt),
+            [
+                "  Release",
+                "    x Write notes",
+                "    Tag",
+                "      Push",
+                "Other"
+            ]
+        );
+        keys(&mut mt, b"u");
+        keys(&mut mt, b"ddp");
+        assert_eq!(
+            lines(&mt),
+            ["Other", "Release", "  x Write notes", "  Tag", "    Push"]
+        );
+        assert_eq!(mt.view, [0, 1, 2, 3, 4]);
+        //The folds went with the lines
+        keys(&mut mt, b"uu");
+        assert_eq!(mt.view, [0, 1, 2, 3, 4]);
+        assert!(mt.folds.is_empty());
+        keys(&mut mt, b"jzo");
+        assert_eq!(mt.message.take().as_deref(), Some("No fold found"));
+    }
+
+    #[test]
+    fn split_join() {
+        let mut mt = headless();
+        for line in ["(A) Plan trip", "  Book hotel and flights", "Other"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        //The new line stays a subtask
+        keys(&mut mt, b"j$bbi\rcar\r\x1b");
+        assert_eq!(
+            lines(&mt),
+            [
+                "(A) Plan trip",
+                "  Book hotel ",
+                "  car",
+                "  and flights",
+                "Other"
+            ]
+        );
+        assert_eq!((mt.curr_row(), mt.cursor.x), (3, 1));
+        keys(&mut mt, b"u");
+        assert_eq!(lines(&mt)[1], "  Book hotel and flights");
+
+        keys(&mut mt, b"ggJ");
+        assert_eq!(
+            lines(&mt),
+            ["(A) Plan trip Book hotel and flights", "Other"]
+        );
+        assert_eq!(mt.cursor.x, 13);
+        keys(&mut mt, b"u3J");
+        assert_eq!(lines(&mt), ["(A) Plan trip Book hotel and flights Other"]);
+        keys(&mut mt, b"J");
+        assert_eq!(lines(&mt).len(), 1);
+        keys(&mut mt, b"ujVjJ");
+        assert_eq!(
+            lines(&mt),
+            ["(A) Plan trip", "  Book hotel and flights Other"]
+        );
+
+        //Splitting at the start moves the whole line down
+        keys(&mut mt, b"0i\r\x1b");
+        assert_eq!(
+            lines(&mt),
+            ["(A) Plan trip", "", "  Book hotel and flights Other"]
+        );
+    }
+
+    #[test]
+    fn split_join_markdown() {
+        let dir = std::env::temp_dir().join(format!("min_todo_{}_split", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("todo.md"),
+            "# Trip\n- [x] Book hotel\n  - [ ] car\n",
+        )
+        .unwrap();
+        let mut mt = MinTodo::open(
+            FileManager::new(dir.join("todo.md")).unwrap(),
+            Box::new(MemoryBackend::new(80, 24)),
+        )
+        .unwrap();
+        keys(&mut mt, b"j$a and flights\x1bbbi\r\x1b");
+        assert_eq!(
+            mt.serialize_lines(&mt.view),
+            [
+                "# Trip",
+                "- [x] Book hotel ",
+                "- [ ] and flights",
+                "  - [ ] car"
+            ]
+        );
+        keys(&mut mt, b"J");
+        assert_eq!(
+            mt.serialize_lines(&mt.view),
+            ["# Trip", "- [x] Book hotel ", "- [ ] and flights car"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn change_family() {
+        let mut mt = headless();
+        for line in ["Call mom today", "  Buy milk", "c", "d"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b"wCdad\x1b");
+        assert_eq!(lines(&mt)[0], "Call dad");
+        keys(&mut mt, b"0sF\x1b");
+        assert_eq!(lines(&mt)[0], "Fall dad");
+        keys(&mut mt, b"w2sma\x1b");
+        assert_eq!(lines(&mt)[0], "Fall mad");
+        //Each of them is a single undo step
+        keys(&mut mt, b"u");
+        assert_eq!(lines(&mt)[0], "Fall dad");
+        keys(&mut mt, b"uu");
+        assert_eq!(lines(&mt)[0], "Call mom today");
+
+        //S keeps the indent
+        keys(&mut mt, b"jSBuy bread\x1b");
+        assert_eq!(lines(&mt), ["Call mom today", "  Buy bread", "c", "d"]);
+        keys(&mut mt, b"j2Snew\x1b");
+        assert_eq!(lines(&mt), ["Call mom today", "  Buy bread", "new"]);
+
+        keys(&mut mt, b"gg03rx");
+        assert_eq!(lines(&mt)[0], "xxxl mom today");
+        assert_eq!(mt.cursor.x, 2);
+        //Not enough characters left, nothing happens
+        keys(&mut mt, b"$5ry");
+        assert_eq!(lines(&mt)[0], "xxxl mom today");
+        keys(&mut mt, b"u");
+
+        keys(&mut mt, b"0~");
+        assert_eq!(lines(&mt)[0], "call mom today");
+        assert_eq!(mt.cursor.x, 1);
+        keys(&mut mt, b"20~");
+        assert_eq!(lines(&mt)[0], "cALL MOM TODAY");
+        assert_eq!(mt.cursor.x, 13);
+        keys(&mut mt, b"uu0vw~");
+        assert_eq!(lines(&mt)[0], "cALL Mom today");
+        keys(&mut mt, b"u");
+
+        //Replace mode, Backspace brings back what was there
+        keys(&mut mt, b"0wRdad and dog");
+        assert_eq!(mt.mode, InputMode::Replace);
+        assert_eq!(lines(&mt)[0], "Call dad and dog");
+        keys(&mut mt, b"\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x7f\x1b");
+        assert_eq!(lines(&mt)[0], "Call dad today");
+        assert_eq!(mt.mode, InputMode::Normal);
+        assert_eq!(mt.cursor.x, 7);
+        keys(&mut mt, b"u");
+        assert_eq!(lines(&mt)[0], "Call mom today");
+        keys(&mut mt, b"03Rab\x1b");
+        assert_eq!(lines(&mt)[0], "abababom today");
+        assert_eq!(mt.cursor.x, 5);
+    }
+
+    #[test]
+    fn repeat() {
+        let mut mt = headless();
+        for line in ["one two three four five six seven", "a", "b", "c", "d", "e"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b"dw.");
+        assert_eq!(lines(&mt)[0], "three four five six seven");
+        //A new count replaces the old one, and sticks
+        keys(&mut mt, b"2.");
+        assert_eq!(lines(&mt)[0], "five six seven");
+        keys(&mut mt, b"u.");
+        assert_eq!(lines(&mt)[0], "five six seven");
+
+        //With what was typed, as a single undo step
+        keys(&mut mt, b"cwnew\x1bw.");
+        assert_eq!(lines(&mt)[0], "new new seven");
+        keys(&mut mt, b"u");
+        assert_eq!(lines(&mt)[0], "new six seven");
+        keys(&mut mt, b"0ix \x1bj.");
+        assert_eq!(lines(&mt)[..3], ["x new six seven", "x a", "b"]);
+        keys(&mut mt, b"gg0x3.");
+        assert_eq!(lines(&mt)[0], "w six seven");
+
+        //Todo commands, and a yank isn't a change
+        keys(&mut mt, b"Gtkk.");
+        assert!(mt.content[3].done && !mt.content[4].done && mt.content[5].done);
+        keys(&mut mt, b"yyj.");
+        assert!(mt.content[4].done);
+        //Including what was typed on the command line they open
+        let due = format!("due:{}", Date::today());
+        keys(&mut mt, b"kgdj.");
+        assert!(mt.content[3].has_word(&due) && mt.content[4].has_word(&due));
+        keys(&mut mt, b"gt+home\rk.");
+        assert!(mt.content[3].has_word("+home") && mt.content[4].has_word("+home"));
+        keys(&mut mt, b"j");
+        //The register goes along
+        keys(&mut mt, b"\"add.");
+        assert_eq!(mt.content.len(), 4);
+        assert!(matches!(
+            mt.registers.get(Some('a')),
+            Some(Register::Linewise(lines)) if lines[0].ends_with(" e")
+        ));
+    }
+
+    #[test]
+    fn macros() {
+        let mut mt = headless();
+        for line in ["Call mom", "Buy milk", "Pay rent", "Fix bike", "Read"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        //Through insert mode and back, the keys are what counts
+        keys(&mut mt, b"qa");
+        assert_eq!(mt.recording.as_ref().map(|r| r.0), Some('a'));
+        keys(&mut mt, b"0i(A) \x1b");
+        keys(&mut mt, b"jq");
+        assert_eq!(mt.recording, None);
+        assert_eq!(lines(&mt)[0], "(A) Call mom");
+        keys(&mut mt, b"@a");
+        assert_eq!(lines(&mt)[1], "(A) Buy milk");
+        assert_eq!(mt.curr_row(), 2);
+        keys(&mut mt, b"2@@");
+        assert_eq!(lines(&mt)[2..4], ["(A) Pay rent", "(A) Fix bike"]);
+        assert_eq!(mt.curr_row(), 4);
+        //The two replays are a single undo step
+        keys(&mut mt, b"u");
+        assert_eq!(lines(&mt)[2..4], ["Pay rent", "Fix bike"]);
+        assert_eq!(lines(&mt)[1], "(A) Buy milk");
+
+        //Macros can play other macros, and append with the uppercase name
+        keys(&mut mt, b"qb0x@aq");
+        assert_eq!(lines(&mt)[2], "(A) ay rent");
+        keys(&mut mt, b"qBq");
+        keys(&mut mt, b"@b");
+        assert_eq!(lines(&mt)[3], "(A) ix bike");
+        //Itself too, until it's nested too deep
+        keys(&mut mt, b"qcj@cq");
+        keys(&mut mt, b"gg@c");
+        assert_eq!(mt.curr_row(), 4);
+        assert_eq!(mt.message.take().as_deref(), Some("Macros nested too deep"));
+        keys(&mut mt, b"@z");
+        assert_eq!(mt.message.take().as_deref(), Some("Nothing in register z"));
+
+        //Recording goes on in visual mode, and can stop there
+        keys(&mut mt, b"qdvlq");
+        assert_eq!(mt.recording, None);
+        assert_eq!(mt.mode, InputMode::Visual);
+        keys(&mut mt, b"v");
+        assert!(matches!(
+            mt.registers.get_macro('d'),
+            Some(keys) if keys.len() == 2
+        ));
+
+        //q doesn't quit
+        keys(&mut mt, b"q\x1b");
+        assert_eq!(mt.recording, None);
+        assert_eq!(mt.mode, InputMode::Normal);
+    }
+
+    #[test]
+    fn marks() {
+        let mut mt = headless();
+        for line in ["one", "  two", "three", "four", "five", "six"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b"j3lma");
+        assert_eq!(mt.marks.get(&'a'), Some(&(1, 3)));
+        //' goes to the first character of the line, ` to the column
+        keys(&mut mt, b"G'a");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (1, 2));
+        keys(&mut mt, b"G`a");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (1, 3));
+
+        //The mark stays on its line when others come and go
+        keys(&mut mt, b"ggO");
+        assert_eq!(mt.marks.get(&'a'), Some(&(2, 3)));
+        keys(&mut mt, b"Gdd`a");
+        assert_eq!((mt.curr_line_nr(), mt.cursor.x), (2, 3));
+        keys(&mut mt, b"ggdd");
+        assert_eq!(mt.marks.get(&'a'), Some(&(1, 3)));
+        //And goes away with it
+        keys(&mut mt, b"jdd'a");
+        assert_eq!(mt.marks.get(&'a'), None);
+        assert_eq!(mt.message.take().as_deref(), Some("Mark not set"));
+        assert_eq!(mt.curr_line_nr(), 1);
+
+        //Marks are motions for operators too
+        assert_eq!(lines(&mt), ["one", "three", "four", "five"]);
+        keys(&mut mt, b"mbGd'b");
+        assert_eq!(lines(&mt), ["one"]);
+        keys(&mut mt, b"d'z");
+        assert_eq!(lines(&mt), ["one"]);
+
+        //Undo and redo move them back and forth, and bring back the ones deleted with the line
+        let mut mt = headless();
+        for line in ["a", "b", "c", "d"] {
+            mt.add_line(TodoItem::from(line));
+        }
+        keys(&mut mt, b"jjmcggdd");
+        assert_eq!(mt.marks.get(&'c'), Some(&(1, 0)));
+        keys(&mut mt, b"u");
+        assert_eq!(mt.marks.get(&'c'), Some(&(2, 0)));
+        keys(&mut mt, b"\x12");
+        assert_eq!(mt.marks.get(&'c'), Some(&(1, 0)));
+        keys(&mut mt, b"ujjdd");
+        assert_eq!(mt.marks.get(&'c'), None);
+        keys(&mut mt, b"u");
+        assert_eq!(mt.marks.get(&'c'), Some(&(2, 0)));
+        keys(&mut mt, b":sort!\r");
+        assert_eq!(lines(&mt), ["d", "c", "b", "a"]);
+        assert_eq!(mt.marks.get(&'c'), Some(&(1, 0)));
+        keys(&mut mt, b"u");
+        assert_eq!(mt.marks.get(&'c'), Some(&(2, 0)));
+    }
+
+    #[test]
+    fn jumps() {
+        let mut mt = headless();
+        for nr in 0..10 {
+            mt.add_line(TodoItem::from(format!("task {nr}").as_str()));
+        }
+        keys(&mut mt, b"Ggg/task 5\r");
+        assert_eq!(mt.curr_line_nr(), 5);
+        //Back through where the jumps started, then forward again
+        for (key, line) in [
+            (&b"\x0f"[..], 0),
+            (b"\x0f", 9),
+            (b"\x0f", 9),
+            (b"\t", 0),
+            (b"\t", 5),
+            (b"\t", 5),
+            (b"2\x0f", 9),
+        ] {
+            keys(&mut mt, key);
+            assert_eq!(mt.curr_line_nr(), line, "{key:?}");
+        }
+        //Small motions aren't jumps
+        keys(&mut mt, b"kkj");
+        assert_eq!(mt.jumps, [(9, 0), (0, 0), (5, 0)]);
+        //The jumps move with the lines
+        keys(&mut mt, b"ggdd");
+        assert_eq!(mt.jumps, [(8, 0), (4, 0), (7, 0)]);
+        keys(&mut mt, b"\x0f");
+        assert_eq!(mt.curr_line_nr(), 7);
+    }
+
+    #[test]
+    fn marks_sidecar() {
+        let dir = std::env::temp_dir().join(format!("min_todo_{}_marks", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("todo.txt");
+        let marks_path = dir.join(".todo.txt.marks");
+        std::fs::write(&path, "a\nb\nc\n").unwrap();
+        let open = || {
+            MinTodo::open(
+                FileManager::new(&path).unwrap(),
+                Box::new(MemoryBackend::new(80, 24)),
+            )
+            .unwrap()
+        };
+
+        //Kept when quitting
+        let mut mt = open();
+        keys(&mut mt, b"jmajmb:q\r");
+        assert_eq!(
+            std::fs::read_to_string(&marks_path).unwrap(),
+            "a 2 0\nb 3 0\n"
+        );
+        //Saved with the lines they are on
+        let mut mt = open();
+        assert_eq!(mt.marks.get(&'b'), Some(&(2, 0)));
+        keys(&mut mt, b"ggdd:w\r");
+        assert_eq!(
+            std::fs::read_to_string(&marks_path).unwrap(),
+            "a 1 0\nb 2 0\n"
+        );
+        //Not when the changes are thrown away, they wouldn't fit the file
+        keys(&mut mt, b"ddmc:q!\r");
+        assert_eq!(
+            std::fs::read_to_string(&marks_path).unwrap(),
+            "a 1 0\nb 2 0\n"
+        );
+
+        //Marks past the end of a file changed elsewhere are dropped
+        std::fs::write(&path, "a\n").unwrap();
+        let mt = open();
+        assert_eq!(mt.marks, Marks::from([('a', (0, 0))]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn operators() {
+        let mut mt = headless();
+        for line in ["one two three four five six seven", "a", "b", "c", "d"] {
+            mt.add_line(TodoItem::from(line));
+        }
+
+        keys(&mut mt, b"dw");
+        assert_eq!(mt.content[0].to_string(), "two three four five six seven");
+        keys(&mut mt, b"2d2w");
+        assert_eq!(mt.content[0].to_string(), "six seven");
+        keys(&mut mt, b"wd$");
+        assert_eq!(mt.content[0].to_string(), "six ");
+        assert_eq!(
+            mt.registers.get(None),
+            Some(&Register::Charwise("seven".into()))
+        );
+        keys(&mut mt, b"$d0");
+        assert_eq!(mt.content[0].to_string(), " ");
+        keys(&mut mt, b"u0cwnew\x1b");
+        assert_eq!(mt.content[0].to_string(), "new ");
+
+        //Linewise
+        keys(&mut mt, b"jdj");
+        assert_eq!(lines(&mt), ["new ", "c", "d"]);
+        assert_eq!(mt.curr_line_nr(), 1);
+        keys(&mut mt, b"Gdk");
+        assert_eq!(lines(&mt), ["new "]);
+        keys(&mut mt, b"uu");
+        keys(&mut mt, b"yGggP");
+        assert_eq!(lines(&mt), ["a", "b", "c", "d", "new ", "a", "b", "c", "d"]);
+        keys(&mut mt, b"4Gdgg");
+        assert_eq!(lines(&mt), ["new ", "a", "b", "c", "d"]);
+        keys(&mut mt, b"d2G");
+        assert_eq!(lines(&mt), ["b", "c", "d"]);
+
+        keys(&mut mt, b"2>>j>k");
+        assert_eq!(lines(&mt), ["    b", "    c", "d"]);
+        keys(&mut mt, b"<G");
+        assert_eq!(lines(&mt), ["  b", "  c", "d"]);
+        keys(&mut mt, b"jccnew\x1b");
+        assert_eq!(lines(&mt), ["  b", "  new", "d"]);
+    }
+
+    #[test]
+    fn visual_mode() {
+        let mut mt = headless();
+        for line in ["one two", "(B) a", "b", "c"] {
+            mt.add_line(TodoItem::from(line));
+        }
+
+        keys(&mut mt, b"lvl");
+        assert_eq!(mt.mode, InputMode::Visual);
+        assert_eq!(mt.selected_span(0), Some((1, 3)));
+        assert_eq!(mt.selected_span(1), None);
+        keys(&mut mt, b"y");
+        assert_eq!(mt.mode, InputMode::Normal);
+        assert_eq!(
+            mt.registers.get(None),
+            Some(&Register::Charwise("ne".into()))
+        );
+        assert_eq!(mt.cursor.x, 1);
+        keys(&mut mt, b"vwd");
+        assert_eq!(lines(&mt)[0], "owo");
+
+        //Several lines are always whole lines
+        keys(&mut mt, b"vj");
+        assert_eq!(mt.selected_span(0), Some((0, 3)));
+        assert_eq!(mt.selected_span(1), Some((0, 5)));
+        keys(&mut mt, b"\x1bV");
+        assert_eq!(mt.mode, InputMode::VisualLine);
+        keys(&mut mt, b"vV");
+        assert_eq!(mt.mode, InputMode::VisualLine);
+        keys(&mut mt, b"V");
+        assert_eq!(mt.mode, InputMode::Normal);
+
+        //Todo commands
+        keys(&mut mt, b"Vjt");
+        assert!(mt.content[1].done && mt.content[2].done && !mt.content[3].done);
+        keys(&mut mt, b"uVj2+");
+        assert_eq!(mt.content[1].priority, Some('A'));
+        assert_eq!(mt.content[2].priority, Some('A'));
+        assert_eq!(mt.content[3].priority, None);
+        keys(&mut mt, b"VGgt");
+        assert_eq!(mt.mode, InputMode::Command);
+        mt.command_line.set_text("tag @home");
+        mt.handle_command_mode_command(CommandModeCommand::Execute);
+        assert_eq!(lines(&mt), ["owo", "(A) a @home", "(A) b @home", "c @home"]);
+
+        //Operators
+        keys(&mut mt, b"ggVj>");
+        assert_eq!(lines(&mt)[..2], ["  owo", "  (A) a @home"]);
+        keys(&mut mt, b"jjVkojd");
+        assert_eq!(lines(&mt), ["  owo"]);
+        keys(&mut mt, b"Vcnew\x1b");
+        assert_eq!(lines(&mt), ["  new"]);
+    }
 }
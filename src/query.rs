@@ -0,0 +1,273 @@
+//Queries for the filtered view, e.g. `is:open +backend due:<=+7`.
+//Terms are separated by spaces and all of them have to match, a leading - negates one:
+//  is:done, is:open        completion, only tasks are open or done
+//  +project, @context      the item has the tag
+//  pri:A, pri:A-C, pri:none
+//  due:, created:, done:   a date, optionally after <, <=, > or >=. The date is YYYY-MM-DD,
+//                          today or +N/-N days from today. any and none check it's there at all.
+//  key:value               any other metadata
+//  anything else is looked for in the description, the case doesn't matter
+use crate::{
+    date::Date,
+    todo_item::{ItemKind, Tag, TodoItem},
+};
+use std::{cmp::Ordering, error::Error, fmt};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Query {
+    text: String,
+    terms: Vec<(bool, Term)>, //Negated or not
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Term {
+    Done(bool),
+    Project(String),
+    Context(String),
+    Priority(Option<(char, char)>), //Inclusive range, None for no priority
+    Date(DateField, DateTest),
+    Meta(String, String),
+    Text(String), //Lowercase
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum DateField {
+    Due,
+    Created,
+    Completed,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum DateTest {
+    Any,
+    None,
+    //The date is compared to the value with one of the orderings
+    Compare(&'static [Ordering], DateValue),
+}
+
+//Relative dates are kept relative, so the same query still works tomorrow
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum DateValue {
+    Exact(Date),
+    FromToday(i64),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QueryError {
+    pub term: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid query term: {}", self.term)
+    }
+}
+
+impl Error for QueryError {}
+
+impl DateValue {
+    fn parse(s: &str) -> Option<Self> {
+        if s == "today" {
+            return Some(Self::FromToday(0));
+        }
+        if s.starts_with(['+', '-']) {
+            return s.parse().ok().map(Self::FromToday);
+        }
+        s.parse().ok().map(Self::Exact)
+    }
+
+    fn date(self, today: Date) -> Date {
+        match self {
+            Self::Exact(date) => date,
+            //Every date is before a day that's too far away to write down
+            Self::FromToday(days) => {
+                today
+                    .add_days(days)
+                    .unwrap_or(if days < 0 { Date::MIN } else { Date::MAX })
+            }
+        }
+    }
+}
+
+impl DateTest {
+    fn parse(s: &str) -> Option<Self> {
+        let (orderings, value): (&'static [Ordering], &str) =
+            if let Some(value) = s.strip_prefix("<=") {
+                (&[Ordering::Less, Ordering::Equal], value)
+            } else if let Some(value) = s.strip_prefix(">=") {
+                (&[Ordering::Greater, Ordering::Equal], value)
+            } else if let Some(value) = s.strip_prefix('<') {
+                (&[Ordering::Less], value)
+            } else if let Some(value) = s.strip_prefix('>') {
+                (&[Ordering::Greater], value)
+            } else {
+                match s {
+                    "any" => return Some(Self::Any),
+                    "none" => return Some(Self::None),
+                    _ => (&[Ordering::Equal], s),
+                }
+            };
+        DateValue::parse(value).map(|value| Self::Compare(orderings, value))
+    }
+
+    fn matches(self, date: Option<Date>, today: Date) -> bool {
+        match (self, date) {
+            (Self::Any, date) => date.is_some(),
+            (Self::None, date) => date.is_none(),
+            (Self::Compare(orderings, value), Some(date)) => {
+                orderings.contains(&date.cmp(&value.date(today)))
+            }
+            (Self::Compare(..), None) => false,
+        }
+    }
+}
+
+impl Term {
+    fn parse(word: &str) -> Option<Self> {
+        let priority = |p: &str| p.parse::<char>().ok().filter(char::is_ascii_uppercase);
+        if let Some((key, value)) = word.split_once(':') {
+            let date = |field| DateTest::parse(value).map(|test| Self::Date(field, test));
+            match key {
+                "is" if value == "done" => return Some(Self::Done(true)),
+                "is" if value == "open" => return Some(Self::Done(false)),
+                "is" => return None,
+                "pri" if value == "none" => return Some(Self::Priority(None)),
+                "pri" => {
+                    let (from, to) = value.split_once('-').unwrap_or((value, value));
+                    return Some(Self::Priority(Some((priority(from)?, priority(to)?))));
+                }
+                "due" => return date(DateField::Due),
+                "created" => return date(DateField::Created),
+                "done" => return date(DateField::Completed),
+                _ => {}
+            }
+        }
+        Some(match Tag::parse(word) {
+            Some(Tag::Project(project)) => Self::Project(project.to_string()),
+            Some(Tag::Context(context)) => Self::Context(context.to_string()),
+            Some(Tag::Meta(key, value)) => Self::Meta(key.to_string(), value.to_string()),
+            None => Self::Text(word.to_lowercase()),
+        })
+    }
+
+    fn matches(&self, item: &TodoItem, today: Date) -> bool {
+        match self {
+            Self::Done(done) => item.kind == ItemKind::Task && item.done == *done,
+            Self::Project(project) => item.tags().any(|t| t == Tag::Project(project.as_str())),
+            Self::Context(context) => item.tags().any(|t| t == Tag::Context(context.as_str())),
+            Self::Priority(None) => item.kind == ItemKind::Task && item.priority.is_none(),
+            Self::Priority(Some((from, to))) => {
+                item.priority.is_some_and(|p| (*from..=*to).contains(&p))
+            }
+            Self::Date(field, test) => {
+                let date = match field {
+                    DateField::Due => item.meta("due").and_then(|due| due.parse().ok()),
+                    DateField::Created => item.creation_date,
+                    DateField::Completed => item.completion_date,
+                };
+                test.matches(date, today)
+            }
+            Self::Meta(key, value) => item.meta(key) == Some(value),
+            Self::Text(text) => item.description.to_lowercase().contains(text),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, QueryError> {
+        let terms = text
+            .split_whitespace()
+            .map(|word| {
+                let (negated, term) = match word.strip_prefix('-').filter(|t| !t.is_empty()) {
+                    Some(term) => (true, term),
+                    None => (false, word),
+                };
+                Term::parse(term)
+                    .map(|term| (negated, term))
+                    .ok_or_else(|| QueryError {
+                        term: word.to_string(),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            text: text.trim().to_string(),
+            terms,
+        })
+    }
+
+    pub fn matches(&self, item: &TodoItem, today: Date) -> bool {
+        self.terms
+            .iter()
+            .all(|(negated, term)| term.matches(item, today) != *negated)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Query, QueryError};
+    use crate::{date::Date, todo_item::TodoItem};
+
+    fn filter<'a>(query: &str, lines: &[&'a str]) -> Vec<&'a str> {
+        let today = Date::new(2024, 1, 10).unwrap();
+        let query = Query::parse(query).unwrap();
+        lines
+            .iter()
+            .copied()
+            .filter(|line| query.matches(&TodoItem::from(*line), today))
+            .collect()
+    }
+
+    const LINES: [&str; 5] = [
+        "(A) 2024-01-01 Fix login +backend @work due:2024-01-12",
+        "(C) Write docs +backend",
+        "x 2024-01-09 2024-01-02 Deploy +backend due:2024-01-08",
+        "Call mom @phone due:2024-01-30",
+        "Buy milk",
+    ];
+
+    #[test]
+    fn terms() {
+        assert_eq!(filter("is:open +backend", &LINES), [LINES[0], LINES[1]]);
+        assert_eq!(filter("is:done", &LINES), [LINES[2]]);
+        assert_eq!(filter("-+backend", &LINES), [LINES[3], LINES[4]]);
+        assert_eq!(filter("@work", &LINES), [LINES[0]]);
+        assert_eq!(filter("pri:A-B", &LINES), [LINES[0]]);
+        assert_eq!(filter("pri:C", &LINES), [LINES[1]]);
+        assert_eq!(filter("pri:none -is:done", &LINES), [LINES[3], LINES[4]]);
+        assert_eq!(filter("MILK", &LINES), [LINES[4]]);
+        assert_eq!(filter("due:2024-01-08", &LINES), [LINES[2]]);
+        assert_eq!(filter("", &LINES), LINES);
+    }
+
+    #[test]
+    fn dates() {
+        //Today is 2024-01-10
+        assert_eq!(filter("due:<=+7", &LINES), [LINES[0], LINES[2]]);
+        assert_eq!(filter("due:>today", &LINES), [LINES[0], LINES[3]]);
+        assert_eq!(filter("due:<today is:open", &LINES), Vec::<&str>::new());
+        assert_eq!(filter("due:none", &LINES), [LINES[1], LINES[4]]);
+        assert_eq!(filter("created:any", &LINES), [LINES[0], LINES[2]]);
+        assert_eq!(filter("done:-1", &LINES), [LINES[2]]);
+        assert_eq!(filter("due:<+9223372036854775807", &LINES).len(), 3);
+        assert_eq!(filter("due:>-99999999", &LINES).len(), 3);
+    }
+
+    #[test]
+    fn errors() {
+        for term in ["is:later", "pri:a", "pri:A-", "due:tomorrow", "done:<"] {
+            assert_eq!(
+                Query::parse(&format!("+backend {term}")),
+                Err(QueryError {
+                    term: term.to_string()
+                })
+            );
+        }
+        assert_eq!(Query::parse(" @work  ").unwrap().to_string(), "@work");
+    }
+}
@@ -0,0 +1,195 @@
+use crate::char_parser::Character;
+use std::collections::{HashMap, VecDeque};
+
+//Lines are kept as they are written in the file, so they can be put into a file of any format
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Register {
+    Linewise(Vec<String>),
+    Charwise(String),
+}
+
+impl Register {
+    //Appending to a register holding lines (or appending lines) makes the whole register linewise
+    fn append(self, other: Register) -> Register {
+        match (self, other) {
+            (Register::Charwise(mut a), Register::Charwise(b)) => {
+                a.push_str(&b);
+                Register::Charwise(a)
+            }
+            (a, b) => {
+                let mut lines = a.into_lines();
+                lines.extend(b.into_lines());
+                Register::Linewise(lines)
+            }
+        }
+    }
+
+    fn into_lines(self) -> Vec<String> {
+        match self {
+            Register::Linewise(lines) => lines,
+            Register::Charwise(text) => vec![text],
+        }
+    }
+}
+
+const DELETE_RING_SIZE: usize = 9;
+
+//Works like the registers in vim:
+//'"' is the unnamed register, set by every yank and delete,
+//'a'-'z' are named registers, 'A'-'Z' append to them,
+//'0' holds the last yank, '1'-'9' the last line deletes and '-' the last delete within a line.
+//Macros are kept apart from the text, as the keys they were typed with.
+#[derive(Debug, Default)]
+pub struct Registers {
+    unnamed: Option<Register>,
+    named: HashMap<char, Register>,
+    yank: Option<Register>,
+    delete_ring: VecDeque<Register>,
+    small_delete: Option<Register>,
+    macros: HashMap<char, Vec<Character>>,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_valid_name(name: char) -> bool {
+        name.is_ascii_alphanumeric() || name == '"' || name == '-'
+    }
+
+    //Stores into a named register if one was given, the unnamed register then
+    //gets the whole content of it, like in vim. Returns false if no name was given.
+    fn store_named(&mut self, name: Option<char>, register: &Register) -> bool {
+        let (name, append) = match name {
+            Some(name @ 'a'..='z') => (name, false),
+            Some(name @ 'A'..='Z') => (name.to_ascii_lowercase(), true),
+            _ => return false,
+        };
+        let register = match self.named.remove(&name) {
+            Some(old) if append => old.append(register.clone()),
+            _ => register.clone(),
+        };
+        self.unnamed = Some(register.clone());
+        self.named.insert(name, register);
+        true
+    }
+
+    pub fn yank(&mut self, name: Option<char>, register: Register) {
+        if !self.store_named(name, &register) {
+            self.yank = Some(register.clone());
+            self.unnamed = Some(register);
+        }
+    }
+
+    pub fn delete(&mut self, name: Option<char>, register: Register) {
+        if !self.store_named(name, &register) {
+            match register {
+                Register::Linewise(_) => {
+                    self.delete_ring.push_front(register.clone());
+                    self.delete_ring.truncate(DELETE_RING_SIZE);
+                }
+                Register::Charwise(_) => self.small_delete = Some(register.clone()),
+            }
+            self.unnamed = Some(register);
+        }
+    }
+
+    //'A'-'Z' append to the macro in 'a'-'z'
+    pub fn record(&mut self, name: char, keys: Vec<Character>) {
+        let recorded = self.macros.entry(name.to_ascii_lowercase()).or_default();
+        if !name.is_ascii_uppercase() {
+            recorded.clear();
+        }
+        recorded.extend(keys);
+    }
+
+    pub fn get_macro(&self, name: char) -> Option<&Vec<Character>> {
+        self.macros.get(&name.to_ascii_lowercase())
+    }
+
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        match name {
+            None | Some('"') => self.unnamed.as_ref(),
+            Some(name @ ('a'..='z' | 'A'..='Z')) => self.named.get(&name.to_ascii_lowercase()),
+            Some('0') => self.yank.as_ref(),
+            Some(name @ '1'..='9') => self.delete_ring.get(name as usize - '1' as usize),
+            Some('-') => self.small_delete.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Register, Registers};
+    use crate::char_parser::Character;
+
+    fn lines(lines: &[&str]) -> Register {
+        Register::Linewise(lines.iter().map(|l| l.to_string()).collect())
+    }
+
+    #[test]
+    fn yank_and_delete() {
+        let mut registers = Registers::new();
+        assert_eq!(registers.get(None), None);
+
+        registers.yank(None, lines(&["first"]));
+        registers.delete(None, lines(&["second"]));
+        registers.delete(None, Register::Charwise(String::from("x")));
+        assert_eq!(
+            registers.get(None),
+            Some(&Register::Charwise(String::from("x")))
+        );
+        assert_eq!(registers.get(Some('"')), registers.get(None));
+        assert_eq!(registers.get(Some('0')), Some(&lines(&["first"])));
+        assert_eq!(registers.get(Some('1')), Some(&lines(&["second"])));
+        assert_eq!(
+            registers.get(Some('-')),
+            Some(&Register::Charwise(String::from("x")))
+        );
+
+        for i in 0..10 {
+            registers.delete(None, lines(&[&i.to_string()]));
+        }
+        assert_eq!(registers.get(Some('1')), Some(&lines(&["9"])));
+        assert_eq!(registers.get(Some('9')), Some(&lines(&["1"])));
+    }
+
+    #[test]
+    fn named() {
+        let mut registers = Registers::new();
+        registers.yank(Some('a'), Register::Charwise(String::from("ab")));
+        registers.yank(Some('A'), Register::Charwise(String::from("cd")));
+        assert_eq!(
+            registers.get(Some('a')),
+            Some(&Register::Charwise(String::from("abcd")))
+        );
+        assert_eq!(registers.get(None), registers.get(Some('a')));
+        //Named yanks don't touch the yank register
+        assert_eq!(registers.get(Some('0')), None);
+
+        registers.delete(Some('A'), lines(&["line"]));
+        assert_eq!(registers.get(Some('a')), Some(&lines(&["abcd", "line"])));
+        assert_eq!(registers.get(Some('1')), None);
+    }
+
+    #[test]
+    fn macros() {
+        let mut registers = Registers::new();
+        registers.record('q', vec![Character::Display('x')]);
+        registers.record('Q', vec![Character::Esc]);
+        assert_eq!(
+            registers.get_macro('q'),
+            Some(&vec![Character::Display('x'), Character::Esc])
+        );
+        registers.record('q', vec![Character::Display('j')]);
+        assert_eq!(
+            registers.get_macro('Q'),
+            Some(&vec![Character::Display('j')])
+        );
+        //The text registers are separate
+        assert_eq!(registers.get(Some('q')), None);
+        assert_eq!(registers.get_macro('a'), None);
+    }
+}
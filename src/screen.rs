@@ -0,0 +1,312 @@
+//What's on screen, kept as a grid of cells so it doesn't depend on the terminal it's drawn to
+use crate::text;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    #[default]
+    Reset,
+    Blue,
+    Green,
+    Cyan,
+    Yellow,
+    Black,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub faint: bool,
+    pub invert: bool,
+}
+
+impl Style {
+    pub fn bold() -> Self {
+        Self {
+            bold: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn faint() -> Self {
+        Self {
+            faint: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn invert() -> Self {
+        Self {
+            invert: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn fg(self, fg: Color) -> Self {
+        Self { fg, ..self }
+    }
+
+    pub fn bg(self, bg: Color) -> Self {
+        Self { bg, ..self }
+    }
+}
+
+//Text in a single style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Span {
+    pub fn new<T: Into<String>>(text: T, style: Style) -> Self {
+        Self {
+            text: text.into(),
+            style,
+        }
+    }
+
+    pub fn plain<T: Into<String>>(text: T) -> Self {
+        Self::new(text, Style::default())
+    }
+}
+
+//The graphemes from..to of the text the spans make up together
+pub fn slice_spans(spans: &[Span], from: usize, to: usize) -> Vec<Span> {
+    let mut sliced = Vec::new();
+    let mut start = 0;
+    for span in spans {
+        let len = text::len(&span.text);
+        let (a, b) = (from.max(start), to.min(start + len));
+        if a < b {
+            sliced.push(Span::new(
+                text::slice(&span.text, a - start, b - start),
+                span.style,
+            ));
+        }
+        start += len;
+    }
+    sliced
+}
+
+//The same text with the graphemes from..to in another style
+pub fn restyle_spans(spans: &[Span], from: usize, to: usize, style: Style) -> Vec<Span> {
+    let mut restyled = slice_spans(spans, 0, from);
+    restyled.extend(
+        slice_spans(spans, from, to)
+            .into_iter()
+            .map(|span| Span::new(span.text, style)),
+    );
+    restyled.extend(slice_spans(spans, to, usize::MAX));
+    restyled
+}
+
+//A grapheme and its style. The cell after a wide grapheme is covered by it and has an empty symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub symbol: String,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: String::from(" "),
+            style: Style::default(),
+        }
+    }
+}
+
+impl Cell {
+    pub fn is_continuation(&self) -> bool {
+        self.symbol.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> &Cell {
+        &self.cells[y * self.width + x]
+    }
+
+    //All cells with their position, row by row
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize, &Cell)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| (i % self.width, i / self.width, cell))
+    }
+
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = cell;
+        }
+    }
+
+    //Replaces the row with the spans, cut off at the right edge
+    pub fn set_line(&mut self, y: usize, spans: &[Span]) {
+        if y >= self.height {
+            return;
+        }
+        let row = y * self.width..(y + 1) * self.width;
+        self.cells[row].fill(Cell::default());
+        let mut x = 0;
+        for span in spans {
+            for grapheme in span.text.graphemes(true) {
+                let width = text::grapheme_width(grapheme);
+                if x + width > self.width {
+                    return;
+                }
+                //Control characters (tabs) would move the terminal's cursor
+                let symbol = if grapheme.chars().any(char::is_control) {
+                    " "
+                } else {
+                    grapheme
+                };
+                self.set_cell(
+                    x,
+                    y,
+                    Cell {
+                        symbol: symbol.to_string(),
+                        style: span.style,
+                    },
+                );
+                for x in x + 1..x + width {
+                    self.set_cell(
+                        x,
+                        y,
+                        Cell {
+                            symbol: String::new(),
+                            style: span.style,
+                        },
+                    );
+                }
+                x += width;
+            }
+        }
+    }
+
+    //The cells that differ from the previous frame. A wide grapheme is drawn
+    //from its first cell, so that one is included if only its second changed.
+    pub fn diff<'a>(&'a self, prev: &Grid) -> Vec<(usize, usize, &'a Cell)> {
+        let mut changed: Vec<(usize, usize, &Cell)> = Vec::new();
+        for ((x, y, cell), prev) in self.cells().zip(&prev.cells) {
+            if cell == prev {
+                continue;
+            }
+            if cell.is_continuation()
+                && x > 0
+                && changed.last().map(|c| (c.0, c.1)) != Some((x - 1, y))
+            {
+                changed.push((x - 1, y, self.cell(x - 1, y)));
+            }
+            changed.push((x, y, cell));
+        }
+        changed
+    }
+
+    //The text of a row, without the styles and the spaces at the end
+    #[cfg(test)]
+    pub fn row_text(&self, y: usize) -> String {
+        let row = &self.cells[y * self.width..(y + 1) * self.width];
+        let text: String = row.iter().map(|cell| cell.symbol.as_str()).collect();
+        text.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{restyle_spans, slice_spans, Cell, Grid, Span, Style};
+
+    #[test]
+    fn set_line() {
+        let mut grid = Grid::new(6, 2);
+        grid.set_line(
+            0,
+            &[Span::plain("a\tb"), Span::new("日本語", Style::bold())],
+        );
+        assert_eq!(grid.row_text(0), "a b日");
+        assert_eq!(grid.cell(3, 0).symbol, "日");
+        assert!(grid.cell(3, 0).style.bold);
+        assert!(grid.cell(4, 0).is_continuation());
+        //本 doesn't fit in the last column
+        assert_eq!(grid.cell(5, 0).symbol, " ");
+
+        grid.set_line(0, &[Span::plain("x")]);
+        assert_eq!(grid.row_text(0), "x");
+        assert_eq!(grid.row_text(1), "");
+    }
+
+    #[test]
+    fn slice() {
+        let spans = [
+            Span::plain("ab"),
+            Span::new("日本", Style::bold()),
+            Span::plain("c"),
+        ];
+        assert_eq!(
+            slice_spans(&spans, 1, 3),
+            [Span::plain("b"), Span::new("日", Style::bold())]
+        );
+        assert_eq!(slice_spans(&spans, 4, usize::MAX), [Span::plain("c")]);
+        assert!(slice_spans(&spans, 5, 9).is_empty());
+        assert_eq!(
+            restyle_spans(&spans, 1, 3, Style::invert()),
+            [
+                Span::plain("a"),
+                Span::new("b", Style::invert()),
+                Span::new("日", Style::invert()),
+                Span::new("本", Style::bold()),
+                Span::plain("c")
+            ]
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let mut prev = Grid::new(4, 2);
+        prev.set_line(0, &[Span::plain("ab")]);
+        prev.set_line(1, &[Span::plain("日x")]);
+        let mut next = prev.clone();
+        assert!(next.diff(&prev).is_empty());
+
+        next.set_line(0, &[Span::plain("ac")]);
+        let changed: Vec<_> = next.diff(&prev).iter().map(|c| (c.0, c.1)).collect();
+        assert_eq!(changed, [(1, 0)]);
+
+        //The wide 本 covers the cell after it too
+        next.set_line(1, &[Span::plain("日本")]);
+        let changed: Vec<_> = next.diff(&prev).iter().map(|c| (c.0, c.1)).collect();
+        assert_eq!(changed, [(1, 0), (2, 1), (3, 1)]);
+
+        //A continuation whose wide grapheme is the same is still drawn from the start
+        let mut broken = next.clone();
+        broken.set_cell(3, 1, Cell::default());
+        let changed: Vec<_> = next.diff(&broken).iter().map(|c| (c.0, c.1)).collect();
+        assert_eq!(changed, [(2, 1), (3, 1)]);
+    }
+}
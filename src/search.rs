@@ -0,0 +1,97 @@
+//Finding a pattern in the lines, for / and ?. Positions are graphemes like the cursor's.
+use crate::text;
+use regex::{Regex, RegexBuilder};
+
+//Smart-case: the case is ignored unless the pattern has an uppercase letter in it
+pub fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!pattern.chars().any(char::is_uppercase))
+        .build()
+}
+
+//Start and end of every match in the line. Empty matches are left out, there'd be
+//nothing to highlight and the cursor would never get past them.
+pub fn matches(regex: &Regex, line: &str) -> Vec<(usize, usize)> {
+    regex
+        .find_iter(line)
+        .filter(|m| !m.is_empty())
+        .map(|m| {
+            (
+                text::grapheme_idx(line, m.start()),
+                text::grapheme_idx(line, m.end()),
+            )
+        })
+        .collect()
+}
+
+//Where the first match after (line, x) starts, or the last one before it going backwards.
+//Like vim it wraps around the end of the lines, the bool tells if it did.
+pub fn find(
+    regex: &Regex,
+    lines: &[String],
+    (line, x): (usize, usize),
+    forward: bool,
+) -> Option<((usize, usize), bool)> {
+    let n = lines.len();
+    let starts = |i: usize| {
+        matches(regex, &lines[i])
+            .into_iter()
+            .map(|(start, _)| start)
+    };
+    if forward {
+        if let Some(start) = starts(line).find(|start| *start > x) {
+            return Some(((line, start), false));
+        }
+        //The current line comes last again, for the matches before the cursor
+        (1..=n).map(|i| (line + i) % n).find_map(|i| {
+            let start = starts(i).next()?;
+            Some(((i, start), i <= line))
+        })
+    } else {
+        if let Some(start) = starts(line).rev().find(|start| *start < x) {
+            return Some(((line, start), false));
+        }
+        (1..=n).map(|i| (line + n - i) % n).find_map(|i| {
+            let start = starts(i).next_back()?;
+            Some(((i, start), i >= line))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compile, find, matches};
+
+    #[test]
+    fn smart_case() {
+        let line = "Call Bob, call again";
+        assert_eq!(matches(&compile("call").unwrap(), line), [(0, 4), (10, 14)]);
+        assert_eq!(matches(&compile("Call").unwrap(), line), [(0, 4)]);
+        assert_eq!(matches(&compile("b.b").unwrap(), line), [(5, 8)]);
+        //Empty matches are skipped
+        assert!(matches(&compile("x*").unwrap(), line).is_empty());
+        assert!(compile("(").is_err());
+        //Graphemes, not bytes
+        assert_eq!(matches(&compile("ok").unwrap(), "日本 ok"), [(3, 5)]);
+    }
+
+    #[test]
+    fn find_wraps() {
+        let lines: Vec<String> = ["a foo", "bar", "foo foo"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let regex = compile("foo").unwrap();
+        assert_eq!(find(&regex, &lines, (0, 0), true), Some(((0, 2), false)));
+        assert_eq!(find(&regex, &lines, (0, 2), true), Some(((2, 0), false)));
+        assert_eq!(find(&regex, &lines, (2, 0), true), Some(((2, 4), false)));
+        assert_eq!(find(&regex, &lines, (2, 4), true), Some(((0, 2), true)));
+        assert_eq!(find(&regex, &lines, (2, 4), false), Some(((2, 0), false)));
+        assert_eq!(find(&regex, &lines, (1, 0), false), Some(((0, 2), false)));
+        assert_eq!(find(&regex, &lines, (0, 2), false), Some(((2, 4), true)));
+        //The only match is under the cursor, it's found again after going all the way around
+        let lines = vec![String::from("foo")];
+        assert_eq!(find(&regex, &lines, (0, 0), true), Some(((0, 0), true)));
+        assert_eq!(find(&compile("x").unwrap(), &lines, (0, 0), true), None);
+    }
+}
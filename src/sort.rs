@@ -0,0 +1,286 @@
+//Ordering items for :sort. The keys are compared one after the other, the first one
+//that isn't equal decides. A - in front of a key reverses it.
+//  pri      A first, no priority last
+//  due      earliest first, no due date last
+//  created  the same for the creation date
+//  done     open before done
+//  project  the first project by name, none last
+//  text     the description, ignoring case
+//Subtasks stay under their parent, see order.
+use crate::{
+    date::Date,
+    todo_item::{ItemKind, TodoItem},
+    tree,
+};
+use std::cmp::Ordering;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum SortField {
+    Priority,
+    Due,
+    Created,
+    Done,
+    Project,
+    Text,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SortKey {
+    field: SortField,
+    reverse: bool,
+}
+
+//Without any keys, like todo.txt tools list them
+pub const DEFAULT_KEYS: [SortKey; 4] = [
+    SortKey::new(SortField::Done),
+    SortKey::new(SortField::Priority),
+    SortKey::new(SortField::Due),
+    SortKey::new(SortField::Text),
+];
+
+//Missing values go after the others
+fn none_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn due(item: &TodoItem) -> Option<Date> {
+    item.meta("due").and_then(|due| due.parse().ok())
+}
+
+impl SortKey {
+    const fn new(field: SortField) -> Self {
+        Self {
+            field,
+            reverse: false,
+        }
+    }
+
+    //Returns the word that isn't a key on error
+    pub fn parse_list(keys: &str) -> Result<Vec<Self>, String> {
+        keys.split_whitespace()
+            .map(|word| {
+                let (reverse, name) = match word.strip_prefix('-') {
+                    Some(name) => (true, name),
+                    None => (false, word),
+                };
+                let field = match name {
+                    "pri" | "priority" => SortField::Priority,
+                    "due" => SortField::Due,
+                    "created" => SortField::Created,
+                    "done" => SortField::Done,
+                    "project" => SortField::Project,
+                    "text" => SortField::Text,
+                    _ => return Err(word.to_string()),
+                };
+                Ok(Self { field, reverse })
+            })
+            .collect()
+    }
+
+    fn compare(self, a: &TodoItem, b: &TodoItem) -> Ordering {
+        let ordering = match self.field {
+            SortField::Priority => none_last(a.priority, b.priority),
+            SortField::Due => none_last(due(a), due(b)),
+            SortField::Created => none_last(a.creation_date, b.creation_date),
+            SortField::Done => a.done.cmp(&b.done),
+            SortField::Project => none_last(
+                a.projects().map(str::to_lowercase).min(),
+                b.projects().map(str::to_lowercase).min(),
+            ),
+            SortField::Text => a
+                .description
+                .to_lowercase()
+                .cmp(&b.description.to_lowercase()),
+        };
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+pub fn compare(keys: &[SortKey], a: &TodoItem, b: &TodoItem) -> Ordering {
+    keys.iter()
+        .map(|key| key.compare(a, b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+//The indices of the items in their sorted order. A task moves with its subtree and its
+//subtasks are sorted among themselves. Only selected tasks move, into the places selected
+//tasks had among the children of the same parent at the same level and between the same
+//notes, the rest stays put.
+pub fn order(items: &[TodoItem], selected: &[bool], keys: &[SortKey], reverse: bool) -> Vec<usize> {
+    let levels = tree::levels(items);
+    //The last entry has the lines without a parent
+    let mut children = vec![Vec::new(); items.len() + 1];
+    let mut ancestors: Vec<usize> = Vec::new();
+    for idx in 0..items.len() {
+        while ancestors.last().is_some_and(|a| levels[*a] >= levels[idx]) {
+            ancestors.pop();
+        }
+        children[ancestors.last().copied().unwrap_or(items.len())].push(idx);
+        ancestors.push(idx);
+    }
+
+    for siblings in &mut children {
+        //Notes like Markdown headings split the siblings into sections, a task doesn't leave
+        //its own one
+        let mut sections = vec![Vec::new()];
+        for (i, idx) in siblings.iter().enumerate() {
+            if items[*idx].kind != ItemKind::Task {
+                sections.push(Vec::new());
+            } else if selected[*idx] {
+                sections.last_mut().unwrap().push(i);
+            }
+        }
+        for movable in sections {
+            //With uneven indentation a deeper sibling could end up under a shallower one
+            let mut sibling_levels: Vec<usize> =
+                movable.iter().map(|i| levels[siblings[*i]]).collect();
+            sibling_levels.sort_unstable();
+            sibling_levels.dedup();
+            for level in sibling_levels {
+                let slots: Vec<usize> = movable
+                    .iter()
+                    .copied()
+                    .filter(|i| levels[siblings[*i]] == level)
+                    .collect();
+                let mut lines: Vec<usize> = slots.iter().map(|i| siblings[*i]).collect();
+                lines.sort_by(|a, b| {
+                    let ordering = compare(keys, &items[*a], &items[*b]);
+                    if reverse {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                });
+                for (slot, line) in slots.into_iter().zip(lines) {
+                    siblings[slot] = line;
+                }
+            }
+        }
+    }
+
+    //Every line followed by its children, depth first
+    let mut order = Vec::with_capacity(items.len());
+    let mut stack: Vec<usize> = children[items.len()].iter().rev().copied().collect();
+    while let Some(idx) = stack.pop() {
+        order.push(idx);
+        stack.extend(children[idx].iter().rev());
+    }
+    order
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compare, order, SortKey, DEFAULT_KEYS};
+    use crate::{markdown, todo_item::TodoItem};
+
+    fn sorted(keys: &str, lines: &[&str]) -> Vec<String> {
+        let keys = SortKey::parse_list(keys).unwrap();
+        let keys = if keys.is_empty() {
+            DEFAULT_KEYS.to_vec()
+        } else {
+            keys
+        };
+        let mut items: Vec<TodoItem> = lines.iter().map(|line| TodoItem::from(*line)).collect();
+        items.sort_by(|a, b| compare(&keys, a, b));
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    const LINES: [&str; 5] = [
+        "x 2024-01-03 Done thing +b",
+        "(B) 2024-01-02 Second +a due:2024-02-01",
+        "plain task",
+        "(A) First +b due:2024-03-01",
+        "2024-01-01 Another +a due:2024-01-15",
+    ];
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            sorted("", &LINES),
+            [LINES[3], LINES[1], LINES[4], LINES[2], LINES[0]]
+        );
+        assert_eq!(
+            sorted("due", &LINES),
+            [LINES[4], LINES[1], LINES[3], LINES[0], LINES[2]]
+        );
+        assert_eq!(
+            sorted("-due", &LINES),
+            [LINES[0], LINES[2], LINES[3], LINES[1], LINES[4]]
+        );
+        //Stable, and the next key decides between equal ones
+        assert_eq!(
+            sorted("project", &LINES),
+            [LINES[1], LINES[4], LINES[0], LINES[3], LINES[2]]
+        );
+        assert_eq!(
+            sorted("project -text", &LINES),
+            [LINES[1], LINES[4], LINES[3], LINES[0], LINES[2]]
+        );
+        assert_eq!(
+            sorted("created", &LINES),
+            [LINES[4], LINES[1], LINES[0], LINES[2], LINES[3]]
+        );
+        assert_eq!(sorted("-done", &LINES)[0], LINES[0]);
+        assert_eq!(SortKey::parse_list("pri size"), Err(String::from("size")));
+    }
+
+    #[test]
+    fn subtrees() {
+        let items: Vec<TodoItem> = [
+            "(B) parent",
+            "  (C) second",
+            "    (A) grandchild",
+            "    (B) other grandchild",
+            "  (A) first",
+            "(C) other",
+            "(A) last",
+        ]
+        .iter()
+        .map(|line| TodoItem::from(*line))
+        .collect();
+        let all = vec![true; items.len()];
+        assert_eq!(
+            order(&items, &all, &DEFAULT_KEYS, false),
+            [6, 0, 4, 1, 2, 3, 5]
+        );
+        assert_eq!(
+            order(&items, &all, &DEFAULT_KEYS, true),
+            [5, 0, 1, 3, 2, 4, 6]
+        );
+        //The subtree of a selected line is sorted with it, unselected lines stay put
+        let selected = [false, true, true, true, true, false, false];
+        assert_eq!(
+            order(&items, &selected, &DEFAULT_KEYS, false),
+            [0, 4, 1, 2, 3, 5, 6]
+        );
+    }
+
+    //Headings and prose keep their tasks, they are only sorted under their own heading
+    #[test]
+    fn sections() {
+        let items = markdown::parse(
+            "# Work\n\
+             - [ ] (C) report\n\
+             - [ ] (A) review\n\
+             Some notes\n\
+             - [ ] (B) call\n\
+             # Home\n\
+             - [ ] plants\n\
+             - [ ] (A) bills\n",
+        );
+        let all = vec![true; items.len()];
+        let keys = SortKey::parse_list("pri").unwrap();
+        assert_eq!(order(&items, &all, &keys, false), [0, 2, 1, 3, 4, 5, 7, 6]);
+        assert_eq!(order(&items, &all, &keys, true), [0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+}
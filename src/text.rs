@@ -0,0 +1,173 @@
+//Positions in a line are counted in grapheme clusters, so an accented letter or an emoji
+//is one step for the cursor. What the terminal needs is the column, where wide characters
+//(CJK, most emoji) take two.
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+pub fn len(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+//Byte offset of the grapheme at idx, the end of the string if idx is past it
+pub fn byte_idx(s: &str, idx: usize) -> usize {
+    s.grapheme_indices(true)
+        .nth(idx)
+        .map_or(s.len(), |(byte, _)| byte)
+}
+
+//The grapheme a byte offset falls into, or the end if it's at the end
+pub fn grapheme_idx(s: &str, byte: usize) -> usize {
+    s.grapheme_indices(true)
+        .take_while(|(start, _)| *start < byte)
+        .count()
+}
+
+pub fn slice(s: &str, from: usize, to: usize) -> &str {
+    &s[byte_idx(s, from)..byte_idx(s, to)]
+}
+
+pub fn replace_range(s: &mut String, from: usize, to: usize, with: &str) {
+    let range = byte_idx(s, from)..byte_idx(s, to);
+    s.replace_range(range, with);
+}
+
+//Inserts at a grapheme position and returns the position right after what was inserted.
+//That's not always idx + len(text), a combining mark joins the grapheme before it.
+pub fn insert(s: &mut String, idx: usize, text: &str) -> usize {
+    let byte = byte_idx(s, idx);
+    s.insert_str(byte, text);
+    grapheme_idx(s, byte + text.len())
+}
+
+//Control characters like tabs are shown as a single space
+pub fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().max(1)
+}
+
+pub fn width(s: &str) -> usize {
+    s.graphemes(true).map(grapheme_width).sum()
+}
+
+//The column the grapheme at idx starts at
+pub fn column(s: &str, idx: usize) -> usize {
+    width(&s[..byte_idx(s, idx)])
+}
+
+//Where each row starts when the line is wrapped at width columns, there's always at least one.
+//A wide grapheme that doesn't fit at the end of a row moves to the next one.
+pub fn wrap(s: &str, width: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut column = 0;
+    for (i, grapheme) in s.graphemes(true).enumerate() {
+        let w = grapheme_width(grapheme);
+        if column + w > width && column > 0 {
+            starts.push(i);
+            column = 0;
+        }
+        column += w;
+    }
+    starts
+}
+
+//Row and column of the grapheme at idx in the wrapped line. Right after a full row
+//(where the insert cursor can be) is the start of the next row.
+pub fn wrapped_position(s: &str, idx: usize, width: usize) -> (usize, usize) {
+    let starts = wrap(s, width);
+    let row = starts.iter().rposition(|start| *start <= idx).unwrap_or(0);
+    let col = column(s, idx) - column(s, starts[row]);
+    if col >= width {
+        (row + 1, 0)
+    } else {
+        (row, col)
+    }
+}
+
+//The grapheme at a column, or the one covering it if it's wide. Past the end it's the length.
+pub fn at_column(s: &str, column: usize) -> usize {
+    let mut end = 0;
+    for (i, grapheme) in s.graphemes(true).enumerate() {
+        end += grapheme_width(grapheme);
+        if end > column {
+            return i;
+        }
+    }
+    len(s)
+}
+
+//Lowercase letters become uppercase and the other way around, for ~
+pub fn toggle_case(s: &str) -> String {
+    let mut toggled = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_lowercase() {
+            toggled.extend(c.to_uppercase());
+        } else {
+            toggled.extend(c.to_lowercase());
+        }
+    }
+    toggled
+}
+
+pub fn graphemes(s: &str) -> Vec<&str> {
+    s.graphemes(true).collect()
+}
+
+//What separates words for w and b
+pub fn is_separator(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_whitespace() || c.is_ascii_punctuation())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let s = "e\u{301}日a🦀";
+        assert_eq!(len(s), 4);
+        assert_eq!(byte_idx(s, 0), 0);
+        assert_eq!(byte_idx(s, 1), 3);
+        assert_eq!(byte_idx(s, 2), 6);
+        assert_eq!(byte_idx(s, 4), s.len());
+        assert_eq!(byte_idx(s, 9), s.len());
+        assert_eq!(grapheme_idx(s, 3), 1);
+        assert_eq!(grapheme_idx(s, s.len()), 4);
+        assert_eq!(slice(s, 1, 3), "日a");
+        assert_eq!(width(s), 6);
+        assert_eq!(column(s, 2), 3);
+        assert_eq!(column(s, 4), 6);
+        assert_eq!(width("a\tb"), 3);
+        assert_eq!(at_column(s, 0), 0);
+        assert_eq!(at_column(s, 2), 1);
+        assert_eq!(at_column(s, 3), 2);
+        assert_eq!(at_column(s, 5), 3);
+        assert_eq!(at_column(s, 6), 4);
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(wrap("", 3), [0]);
+        assert_eq!(wrap("abcdefg", 3), [0, 3, 6]);
+        assert_eq!(wrap("abc", 3), [0]);
+        //日 doesn't fit after ab, so the first row is only two columns wide
+        assert_eq!(wrap("ab日本", 3), [0, 2, 3]);
+        assert_eq!(wrapped_position("ab日本", 1, 3), (0, 1));
+        assert_eq!(wrapped_position("ab日本", 3, 3), (2, 0));
+        assert_eq!(wrapped_position("abc", 3, 3), (1, 0));
+    }
+
+    #[test]
+    fn editing() {
+        let mut s = String::from("日本");
+        assert_eq!(insert(&mut s, 1, "x"), 2);
+        assert_eq!(s, "日x本");
+        //The accent becomes part of the x
+        assert_eq!(insert(&mut s, 2, "\u{301}"), 2);
+        assert_eq!(len(&s), 3);
+        replace_range(&mut s, 1, 2, "");
+        assert_eq!(s, "日本");
+        assert_eq!(toggle_case("Call Mom +Ünï 3"), "cALL mOM +üNÏ 3");
+    }
+}
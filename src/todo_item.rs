@@ -0,0 +1,327 @@
+use crate::{
+    date::Date,
+    format::Format,
+    screen::{Color, Span, Style},
+    todo_txt,
+};
+use std::fmt;
+
+//A single task, parsed from one line of the file.
+//Everything that isn't recognised stays in the description verbatim,
+//so printing the item gives back exactly the line it was parsed from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ItemKind {
+    #[default]
+    Task,
+    //Anything that isn't a task, like markdown headings and prose, kept verbatim in the description
+    Note,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TodoItem {
+    pub kind: ItemKind,
+    pub indent: String,
+    pub bullet: Option<char>, //Markdown list marker
+    pub done: bool,
+    pub priority: Option<char>,
+    pub completion_date: Option<Date>,
+    pub creation_date: Option<Date>,
+    pub description: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Tag<'a> {
+    Project(&'a str),
+    Context(&'a str),
+    Meta(&'a str, &'a str),
+}
+
+impl<'a> Tag<'a> {
+    pub fn parse(word: &'a str) -> Option<Self> {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            Some(Tag::Project(project))
+        } else if let Some(context) = word.strip_prefix('@').filter(|c| !c.is_empty()) {
+            Some(Tag::Context(context))
+        } else {
+            let (key, value) = word.split_once(':')?;
+            //Skip things like 12:30 that are unlikely to be metadata and urls (http://...)
+            if key.is_empty() || value.is_empty() || value.contains(':') || value.starts_with("//")
+            {
+                None
+            } else {
+                Some(Tag::Meta(key, value))
+            }
+        }
+    }
+}
+
+impl TodoItem {
+    //Lines are read as todo.txt, see todo_txt for the stricter variant
+    pub fn parse(line: &str) -> Self {
+        todo_txt::parse_item(line)
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = Tag<'_>> {
+        self.description.split_whitespace().filter_map(Tag::parse)
+    }
+
+    pub fn projects(&self) -> impl Iterator<Item = &str> {
+        self.tags().filter_map(|t| match t {
+            Tag::Project(p) => Some(p),
+            _ => None,
+        })
+    }
+
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.tags().find_map(|t| match t {
+            Tag::Meta(k, v) if k == key => Some(v),
+            _ => None,
+        })
+    }
+
+    pub fn has_word(&self, word: &str) -> bool {
+        self.description.split_whitespace().any(|w| w == word)
+    }
+
+    fn push_word(&mut self, word: &str) {
+        if !self.description.is_empty() && !self.description.ends_with(' ') {
+            self.description.push(' ');
+        }
+        self.description.push_str(word);
+    }
+
+    //Removes every word matching `f`, the rest of the spacing is kept as is
+    fn remove_words<F: Fn(&str) -> bool>(&mut self, f: F) -> bool {
+        let words: Vec<&str> = self.description.split(' ').collect();
+        let kept: Vec<&str> = words.iter().copied().filter(|w| !f(w)).collect();
+        if kept.len() == words.len() {
+            return false;
+        }
+        self.description = kept.join(" ").trim_end().to_string();
+        true
+    }
+
+    //`tag` is written as in the file, e.g. "+project", "@context" or "due:2024-01-01"
+    pub fn add_tag(&mut self, tag: &str) {
+        if !self.has_word(tag) {
+            self.push_word(tag);
+        }
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        self.remove_words(|w| w == tag)
+    }
+
+    pub fn set_meta(&mut self, key: &str, value: Option<&str>) {
+        let is_key = |w: &str| matches!(Tag::parse(w), Some(Tag::Meta(k, _)) if k == key);
+        let new = value.map(|v| format!("{key}:{v}"));
+        match &new {
+            Some(new) if self.meta(key).is_some() => {
+                self.description = self
+                    .description
+                    .split(' ')
+                    .map(|w| if is_key(w) { new.as_str() } else { w })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+            }
+            Some(new) => self.push_word(new),
+            None => {
+                self.remove_words(is_key);
+            }
+        }
+    }
+
+    //Like other todo.txt tools, the priority is kept as pri:X while the item is done
+    pub fn toggle_done(&mut self, today: Date) {
+        if self.done {
+            self.done = false;
+            self.completion_date = None;
+            if let Some(priority) = self
+                .meta("pri")
+                .and_then(|p| p.parse::<char>().ok())
+                .filter(char::is_ascii_uppercase)
+            {
+                self.priority = Some(priority);
+                self.set_meta("pri", None);
+            }
+        } else {
+            self.done = true;
+            self.completion_date = Some(today);
+            if let Some(priority) = self.priority.take() {
+                self.set_meta("pri", Some(&priority.to_string()));
+            }
+        }
+    }
+
+    //A is the highest priority, raising an item without a priority gives it A
+    pub fn raise_priority(&mut self, steps: u8) {
+        if !self.done {
+            self.priority = Some(match self.priority {
+                Some(p) => (p as u8).saturating_sub(steps).max(b'A') as char,
+                None => 'A',
+            });
+        }
+    }
+
+    //Lowering past Z removes the priority
+    pub fn lower_priority(&mut self, steps: u8) {
+        if let Some(p) = self.priority {
+            let p = (p as u8).saturating_add(steps);
+            self.priority = (p <= b'Z').then_some(p as char);
+        }
+    }
+
+    //The item with done/priority/tag highlighting,
+    //the styles don't take any space so the layout stays the same as the plain text
+    pub fn render(&self, format: Format) -> Vec<Span> {
+        if self.kind == ItemKind::Note {
+            let style = if self.description.trim_start().starts_with('#') {
+                Style::bold()
+            } else {
+                Style::default()
+            };
+            return vec![Span::new(self.description.as_str(), style)];
+        }
+
+        //The description always comes last, everything before it is the header
+        let line = format.serialize(self);
+        let header = &line[..line.len() - self.description.len()];
+        //Done items are faint as a whole, the priority only makes the header bold
+        let base = if self.done {
+            Style::faint()
+        } else {
+            Style::default()
+        };
+        let header_style = if !self.done && self.priority.is_some() {
+            Style::bold()
+        } else {
+            base
+        };
+        let mut spans = vec![Span::new(header, header_style)];
+        for (i, word) in self.description.split(' ').enumerate() {
+            if i != 0 {
+                spans.push(Span::new(" ", base));
+            }
+            let style = match Tag::parse(word) {
+                Some(Tag::Project(_)) => base.fg(Color::Blue),
+                Some(Tag::Context(_)) => base.fg(Color::Green),
+                Some(Tag::Meta(..)) => base.fg(Color::Cyan),
+                None => base,
+            };
+            spans.push(Span::new(word, style));
+        }
+        spans
+    }
+}
+
+impl From<&str> for TodoItem {
+    fn from(line: &str) -> Self {
+        Self::parse(line)
+    }
+}
+
+impl fmt::Display for TodoItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&todo_txt::serialize(self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Tag, TodoItem};
+    use crate::date::Date;
+
+    fn item(line: &str) -> TodoItem {
+        TodoItem::parse(line)
+    }
+
+    #[test]
+    fn parse() {
+        let item =
+            TodoItem::parse("x (A) 2024-01-02 2024-01-01 Call mom +family @phone due:2024-01-05");
+        assert!(item.done);
+        assert_eq!(item.priority, Some('A'));
+        assert_eq!(item.completion_date, Date::new(2024, 1, 2));
+        assert_eq!(item.creation_date, Date::new(2024, 1, 1));
+        assert_eq!(item.description, "Call mom +family @phone due:2024-01-05");
+
+        let item = TodoItem::parse("2024-01-01 Open task");
+        assert!(!item.done);
+        assert_eq!(item.creation_date, Date::new(2024, 1, 1));
+        assert_eq!(item.description, "Open task");
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(Tag::parse("+"), None);
+        assert_eq!(Tag::parse("@home"), Some(Tag::Context("home")));
+        assert_eq!(Tag::parse("at 12:30:00"), None);
+        assert_eq!(Tag::parse("https://example.com"), None);
+        assert_eq!(Tag::parse("pri:A"), Some(Tag::Meta("pri", "A")));
+    }
+
+    #[test]
+    fn round_trip() {
+        for line in [
+            "",
+            "x",
+            "x ",
+            "xylophone lessons",
+            "(a) lowercase priority",
+            "(A)no space",
+            "(A) ",
+            "x  2024-01-01 two spaces",
+            "2024-13-01 not a date",
+            "x 2024-01-02 2024-01-01  spaced  out  ",
+            "  indented +project @context key:value",
+            "(B) 2024-01-01 2024-01-02 two dates on an open task",
+        ] {
+            assert_eq!(TodoItem::parse(line).to_string(), line);
+        }
+    }
+
+    #[test]
+    fn edit_tags() {
+        let mut item = item("Buy milk");
+        item.add_tag("+shopping");
+        item.add_tag("+shopping");
+        assert_eq!(item.description, "Buy milk +shopping");
+
+        item.set_meta("due", Some("2024-01-01"));
+        item.set_meta("due", Some("2024-02-01"));
+        assert_eq!(item.description, "Buy milk +shopping due:2024-02-01");
+        assert_eq!(item.meta("due"), Some("2024-02-01"));
+
+        assert!(item.remove_tag("+shopping"));
+        assert!(!item.remove_tag("+shopping"));
+        item.set_meta("due", None);
+        assert_eq!(item.description, "Buy milk");
+    }
+
+    #[test]
+    fn toggle_done() {
+        let today = Date::new(2024, 1, 2).unwrap();
+        let mut item = item("(B) 2024-01-01 Call mom");
+        item.toggle_done(today);
+        assert_eq!(item.to_string(), "x 2024-01-02 2024-01-01 Call mom pri:B");
+        item.toggle_done(today);
+        assert_eq!(item.to_string(), "(B) 2024-01-01 Call mom");
+    }
+
+    #[test]
+    fn priority() {
+        let mut item = item("Call mom");
+        item.lower_priority(1);
+        assert_eq!(item.priority, None);
+        item.raise_priority(1);
+        assert_eq!(item.priority, Some('A'));
+        item.raise_priority(1);
+        assert_eq!(item.priority, Some('A'));
+        item.lower_priority(3);
+        assert_eq!(item.priority, Some('D'));
+        item.raise_priority(2);
+        assert_eq!(item.priority, Some('B'));
+        item.lower_priority(30);
+        assert_eq!(item.priority, None);
+    }
+}
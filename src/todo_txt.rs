@@ -0,0 +1,360 @@
+//The todo.txt format, see https://github.com/todotxt/todo.txt
+use crate::{
+    date::Date,
+    todo_item::{ItemKind, TodoItem},
+};
+use std::{error::Error, fmt};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ParseMode {
+    //Rejects lines other todo.txt tools would read differently
+    Strict,
+    //Never fails, whatever isn't understood is kept in the description
+    #[default]
+    Lenient,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TodoTxtErrorKind {
+    UppercaseCompletionMarker,
+    InvalidPriority(String),
+    InvalidDate(String),
+    PriorityOnCompletedTask,
+    CompletedBeforeCreated,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TodoTxtError {
+    pub line: usize, //1 based
+    pub kind: TodoTxtErrorKind,
+}
+
+impl fmt::Display for TodoTxtErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UppercaseCompletionMarker => {
+                write!(f, "completion marker has to be a lowercase x")
+            }
+            Self::InvalidPriority(p) => write!(f, "invalid priority {p}, expected (A)-(Z)"),
+            Self::InvalidDate(d) => write!(f, "invalid date {d}, expected YYYY-MM-DD"),
+            Self::PriorityOnCompletedTask => write!(f, "completed task has a priority"),
+            Self::CompletedBeforeCreated => write!(f, "task completed before it was created"),
+        }
+    }
+}
+
+impl fmt::Display for TodoTxtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for TodoTxtError {}
+
+//Consumes `token` followed by a single space from the start of `s`
+fn strip_token<'a>(s: &'a str, token: &str) -> Option<&'a str> {
+    s.strip_prefix(token)?.strip_prefix(' ')
+}
+
+fn strip_priority(s: &str) -> Option<(char, &str)> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('('), Some(p @ 'A'..='Z'), Some(')'), Some(' ')) => Some((p, &s[4..])),
+        _ => None,
+    }
+}
+
+fn strip_date(s: &str) -> Option<(Date, &str)> {
+    let date = s.get(..10)?.parse().ok()?;
+    Some((date, strip_token(&s[10..], "")?))
+}
+
+//Something shaped like YYYY-MM-DD, whether or not it is a real date
+fn looks_like_date(word: &str) -> bool {
+    word.len() == 10
+        && word.bytes().enumerate().all(|(i, b)| {
+            if i == 4 || i == 7 {
+                b == b'-'
+            } else {
+                b.is_ascii_digit()
+            }
+        })
+}
+
+//Leading whitespace is the indent, it nests the task under the one above
+pub fn parse_item(line: &str) -> TodoItem {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let mut item = match strip_token(rest, "x") {
+        Some(rest) => parse_fields(true, rest),
+        None => parse_fields(false, rest),
+    };
+    item.indent = line[..line.len() - rest.len()].to_string();
+    item
+}
+
+//Parses what comes after the completion marker, other formats have their own marker.
+//Each part of the header has to be followed by exactly one space,
+//otherwise it is left in the description so nothing is lost.
+pub fn parse_fields(done: bool, line: &str) -> TodoItem {
+    let mut item = TodoItem {
+        done,
+        ..TodoItem::default()
+    };
+    let mut rest = line;
+
+    if let Some((priority, r)) = strip_priority(rest) {
+        item.priority = Some(priority);
+        rest = r;
+    }
+    if let Some((date, r)) = strip_date(rest) {
+        rest = r;
+        if item.done {
+            item.completion_date = Some(date);
+            if let Some((date, r)) = strip_date(rest) {
+                item.creation_date = Some(date);
+                rest = r;
+            }
+        } else {
+            item.creation_date = Some(date);
+        }
+    }
+
+    item.description = rest.to_string();
+    item
+}
+
+fn check(line: &str, item: &TodoItem) -> Result<(), TodoTxtErrorKind> {
+    if line.trim_start_matches([' ', '\t']).starts_with("X ") {
+        return Err(TodoTxtErrorKind::UppercaseCompletionMarker);
+    }
+
+    let first_word = item.description.split(' ').next().unwrap_or_default();
+    let header_done = item.priority.is_some()
+        || item.creation_date.is_some()
+        || (item.done && item.completion_date.is_some());
+    if !header_done && matches!(first_word.as_bytes(), [b'(', c, b')'] if c.is_ascii_alphabetic()) {
+        return Err(TodoTxtErrorKind::InvalidPriority(first_word.to_string()));
+    }
+    if item.creation_date.is_none() && looks_like_date(first_word) {
+        return Err(TodoTxtErrorKind::InvalidDate(first_word.to_string()));
+    }
+
+    if item.done && item.priority.is_some() {
+        return Err(TodoTxtErrorKind::PriorityOnCompletedTask);
+    }
+    if let (Some(completed), Some(created)) = (item.completion_date, item.creation_date) {
+        if completed < created {
+            return Err(TodoTxtErrorKind::CompletedBeforeCreated);
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_line(line: &str, mode: ParseMode) -> Result<TodoItem, TodoTxtErrorKind> {
+    let item = parse_item(line);
+    if mode == ParseMode::Strict {
+        check(line, &item)?;
+    }
+    Ok(item)
+}
+
+pub fn parse(content: &str, mode: ParseMode) -> Result<Vec<TodoItem>, TodoTxtError> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| parse_line(line, mode).map_err(|kind| TodoTxtError { line: i + 1, kind }))
+        .collect()
+}
+
+pub fn serialize(item: &TodoItem) -> String {
+    match item.kind {
+        ItemKind::Note => item.description.clone(),
+        ItemKind::Task if item.done => format!("{}x {}", item.indent, serialize_fields(item)),
+        ItemKind::Task => format!("{}{}", item.indent, serialize_fields(item)),
+    }
+}
+
+//The counterpart of parse_fields
+pub fn serialize_fields(item: &TodoItem) -> String {
+    let mut line = String::new();
+    if let Some(priority) = item.priority {
+        line.push_str(&format!("({priority}) "));
+    }
+    if let Some(date) = item.completion_date {
+        line.push_str(&format!("{date} "));
+    }
+    if let Some(date) = item.creation_date {
+        line.push_str(&format!("{date} "));
+    }
+    line.push_str(&item.description);
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, parse_line, serialize, ParseMode, TodoTxtError, TodoTxtErrorKind};
+    use crate::{date::Date, todo_item::TodoItem};
+
+    macro_rules! strict_test_wrapper {
+        ($name: ident,$line: expr, $out: expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(parse_line($line, ParseMode::Strict).map(|_| ()), $out);
+                //Lenient mode accepts everything
+                assert!(parse_line($line, ParseMode::Lenient).is_ok());
+            }
+        };
+    }
+
+    strict_test_wrapper!(strict_plain, "Call mom", Ok(()));
+    strict_test_wrapper!(
+        strict_full,
+        "x 2024-01-02 2024-01-01 Call mom +family @phone",
+        Ok(())
+    );
+    strict_test_wrapper!(strict_priority_no_space, "(A)->Call mom", Ok(()));
+    strict_test_wrapper!(
+        strict_uppercase_x,
+        "X 2024-01-02 Call mom",
+        Err(TodoTxtErrorKind::UppercaseCompletionMarker)
+    );
+    strict_test_wrapper!(
+        strict_lowercase_priority,
+        "(a) Call mom",
+        Err(TodoTxtErrorKind::InvalidPriority(String::from("(a)")))
+    );
+    strict_test_wrapper!(
+        strict_invalid_date,
+        "(A) 2024-02-30 Call mom",
+        Err(TodoTxtErrorKind::InvalidDate(String::from("2024-02-30")))
+    );
+    strict_test_wrapper!(
+        strict_invalid_creation_date,
+        "x 2024-01-02 2024-01-32 Call mom",
+        Err(TodoTxtErrorKind::InvalidDate(String::from("2024-01-32")))
+    );
+    strict_test_wrapper!(
+        strict_priority_on_completed,
+        "x (A) 2024-01-02 Call mom",
+        Err(TodoTxtErrorKind::PriorityOnCompletedTask)
+    );
+    strict_test_wrapper!(
+        strict_completed_before_created,
+        "x 2024-01-01 2024-01-02 Call mom",
+        Err(TodoTxtErrorKind::CompletedBeforeCreated)
+    );
+
+    #[test]
+    fn parse_file() {
+        let items = parse("(A) first\n\nx second\n", ParseMode::Strict).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].priority, Some('A'));
+        assert_eq!(items[1], TodoItem::default());
+        assert!(items[2].done);
+
+        assert_eq!(
+            parse("ok\n(b) not ok\n", ParseMode::Strict),
+            Err(TodoTxtError {
+                line: 2,
+                kind: TodoTxtErrorKind::InvalidPriority(String::from("(b)"))
+            })
+        );
+    }
+
+    #[test]
+    fn parse_fields() {
+        let item = parse_line(
+            "x 2024-01-02 2024-01-01 Call mom +family due:2024-01-05",
+            ParseMode::Strict,
+        )
+        .unwrap();
+        assert!(item.done);
+        assert_eq!(item.priority, None);
+        assert_eq!(item.completion_date, Date::new(2024, 1, 2));
+        assert_eq!(item.creation_date, Date::new(2024, 1, 1));
+        assert_eq!(item.description, "Call mom +family due:2024-01-05");
+
+        //A single date on a completed task is the completion date
+        let item = parse_line("x 2024-01-02 Call mom", ParseMode::Strict).unwrap();
+        assert_eq!(item.completion_date, Date::new(2024, 1, 2));
+        assert_eq!(item.creation_date, None);
+
+        //Subtasks are indented
+        let item = parse_line("  \tx (A) Call dad", ParseMode::Lenient).unwrap();
+        assert_eq!(item.indent, "  \t");
+        assert!(item.done);
+        assert_eq!(item.priority, Some('A'));
+        assert_eq!(item.description, "Call dad");
+        assert_eq!(
+            parse_line("  X Call dad", ParseMode::Strict),
+            Err(TodoTxtErrorKind::UppercaseCompletionMarker)
+        );
+    }
+
+    //Every combination of (valid and broken) header parts has to come back unchanged
+    #[test]
+    fn round_trip_text() {
+        let markers = ["", "x ", "X ", "x", "x  "];
+        let priorities = ["", "(A) ", "(Z) ", "(a) ", "(A)", "(AB) ", "() "];
+        let first_dates = ["", "2024-01-02 ", "2024-02-30 ", "2024-1-2 ", "2024-01-02"];
+        let second_dates = ["", "2024-01-01 ", "2024-13-01 "];
+        let descriptions = [
+            "",
+            " ",
+            "task",
+            " leading space",
+            "trailing space ",
+            "+project @context key:value",
+            "http://example.com 12:30",
+            "multiple   spaces\tand tabs",
+            "ünïcödé ✓ 日本語",
+        ];
+
+        for marker in markers {
+            for priority in priorities {
+                for first_date in first_dates {
+                    for second_date in second_dates {
+                        for description in descriptions {
+                            let line =
+                                format!("{marker}{priority}{first_date}{second_date}{description}");
+                            let item = parse_line(&line, ParseMode::Lenient).unwrap();
+                            assert_eq!(serialize(&item), line);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    //And serializing an item has to parse back to the same item
+    #[test]
+    fn round_trip_item() {
+        let dates = [None, Date::new(2024, 1, 1), Date::new(2024, 2, 29)];
+        for done in [false, true] {
+            for priority in [None, Some('A'), Some('Z')] {
+                for completion_date in dates {
+                    for creation_date in dates {
+                        for description in ["", "task +project @context due:2024-03-01"] {
+                            //A lone date on a completed task is read as the completion date
+                            if !done && completion_date.is_some()
+                                || done && completion_date.is_none() && creation_date.is_some()
+                            {
+                                continue;
+                            }
+                            let item = TodoItem {
+                                done,
+                                priority,
+                                completion_date,
+                                creation_date,
+                                description: description.to_string(),
+                                ..TodoItem::default()
+                            };
+                            let line = serialize(&item);
+                            assert_eq!(parse_line(&line, ParseMode::Lenient), Ok(item));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
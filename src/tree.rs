@@ -0,0 +1,99 @@
+//Subtasks are the items indented under a task, the lines after it that are nested deeper.
+//Every SHIFT_WIDTH of indentation is a level, a tab counts as one too.
+use crate::todo_item::{ItemKind, TodoItem};
+
+//What > adds and < removes
+pub const SHIFT_WIDTH: &str = "  ";
+
+pub fn level(item: &TodoItem) -> usize {
+    //Notes aren't parsed, their indentation is still part of the text
+    let indent = match item.kind {
+        ItemKind::Task => item.indent.as_str(),
+        ItemKind::Note => {
+            let text = item.description.trim_start_matches([' ', '\t']);
+            &item.description[..item.description.len() - text.len()]
+        }
+    };
+    let columns: usize = indent
+        .chars()
+        .map(|c| if c == '\t' { SHIFT_WIDTH.len() } else { 1 })
+        .sum();
+    columns / SHIFT_WIDTH.len()
+}
+
+pub fn levels(items: &[TodoItem]) -> Vec<usize> {
+    items.iter().map(level).collect()
+}
+
+//The index right after the last line of the subtree at idx
+pub fn subtree_end(levels: &[usize], idx: usize) -> usize {
+    levels[idx + 1..]
+        .iter()
+        .position(|level| *level <= levels[idx])
+        .map_or(levels.len(), |i| idx + 1 + i)
+}
+
+pub fn has_children(levels: &[usize], idx: usize) -> bool {
+    subtree_end(levels, idx) > idx + 1
+}
+
+pub fn parent(levels: &[usize], idx: usize) -> Option<usize> {
+    levels[..idx].iter().rposition(|level| *level < levels[idx])
+}
+
+//How many of the tasks in the subtree are done, and how many there are, at every depth
+pub fn progress(items: &[TodoItem], levels: &[usize], idx: usize) -> (usize, usize) {
+    let tasks = items[idx + 1..subtree_end(levels, idx)]
+        .iter()
+        .filter(|item| item.kind == ItemKind::Task);
+    tasks.fold((0, 0), |(done, total), item| {
+        (done + usize::from(item.done), total + 1)
+    })
+}
+
+//The lines with the subtrees of the ones that have them, in order
+pub fn with_subtrees(levels: &[usize], lines: &[usize]) -> Vec<usize> {
+    let mut all: Vec<usize> = lines
+        .iter()
+        .flat_map(|idx| *idx..subtree_end(levels, *idx))
+        .collect();
+    all.sort_unstable();
+    all.dedup();
+    all
+}
+
+#[cfg(test)]
+mod test {
+    use super::{has_children, level, levels, parent, progress, subtree_end, with_subtrees};
+    use crate::{markdown, todo_item::TodoItem};
+
+    #[test]
+    fn structure() {
+        let items: Vec<TodoItem> = [
+            "Release",
+            "  x Write notes",
+            "  Tag",
+            "\tx Push",
+            "    Announce",
+            "Other",
+        ]
+        .iter()
+        .map(|line| TodoItem::from(*line))
+        .collect();
+        let levels = levels(&items);
+        assert_eq!(levels, [0, 1, 1, 1, 2, 0]);
+        assert_eq!(subtree_end(&levels, 0), 5);
+        assert_eq!(subtree_end(&levels, 3), 5);
+        assert_eq!(subtree_end(&levels, 5), 6);
+        assert!(has_children(&levels, 3) && !has_children(&levels, 2));
+        assert_eq!(parent(&levels, 4), Some(3));
+        assert_eq!(parent(&levels, 2), Some(0));
+        assert_eq!(parent(&levels, 5), None);
+        assert_eq!(progress(&items, &levels, 0), (2, 4));
+        assert_eq!(progress(&items, &levels, 3), (0, 1));
+        assert_eq!(with_subtrees(&levels, &[3, 2]), [2, 3, 4]);
+
+        //Indented markdown prose is a note, it nests like tasks do
+        assert_eq!(level(&markdown::parse_item("    some text")), 2);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::todo_item::TodoItem;
+
+//Where the cursor and the viewport were, restored together with the content
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ViewState {
+    pub cursor_x: usize,
+    pub cursor_y: usize,
+    pub scroll_beg: usize,
+    pub scroll_end: usize,
+}
+
+//The content and the archived items that go to done.txt on the next write
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Snapshot {
+    pub content: Vec<TodoItem>,
+    pub archived: Vec<TodoItem>,
+}
+
+//How a change moved the lines, so the marks can move back and forth with undo and redo
+#[derive(Debug, PartialEq, Clone)]
+pub enum LineShift {
+    //The indices of the new lines, in order, and the marks to put on them
+    Insert {
+        lines: Vec<usize>,
+        marks: Vec<(char, (usize, usize))>,
+    },
+    //The indices of the removed lines before the removal, and the marks that were on them
+    Remove {
+        lines: Vec<usize>,
+        marks: Vec<(char, (usize, usize))>,
+    },
+    Reorder(Vec<usize>), //The new index of every line
+}
+
+impl LineShift {
+    fn inverse(&self) -> Self {
+        match self {
+            Self::Insert { lines, marks } => Self::Remove {
+                lines: lines.clone(),
+                marks: marks.clone(),
+            },
+            Self::Remove { lines, marks } => Self::Insert {
+                lines: lines.clone(),
+                marks: marks.clone(),
+            },
+            Self::Reorder(moved) => {
+                let mut back = vec![0; moved.len()];
+                for (old, new) in moved.iter().enumerate() {
+                    back[*new] = old;
+                }
+                Self::Reorder(back)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct UndoNode {
+    parent: Option<usize>,
+    //Child taken by redo, the most recent one unless we went back another way
+    redo_child: Option<usize>,
+    snapshot: Snapshot,
+    shifts: Vec<LineShift>, //From the parent to this node
+    //View right before and right after the change that lead to this node
+    before: ViewState,
+    after: ViewState,
+}
+
+//Every change group is a snapshot of the whole content.
+//Undoing and then changing something starts a new branch, the old one stays in the tree.
+#[derive(Debug)]
+pub struct UndoTree {
+    nodes: Vec<UndoNode>,
+    current: usize,
+    saved: Option<usize>,
+}
+
+impl UndoTree {
+    pub fn new(content: &[TodoItem]) -> Self {
+        Self {
+            nodes: vec![UndoNode {
+                parent: None,
+                redo_child: None,
+                snapshot: Snapshot {
+                    content: content.to_vec(),
+                    archived: Vec::new(),
+                },
+                shifts: Vec::new(),
+                before: ViewState::default(),
+                after: ViewState::default(),
+            }],
+            current: 0,
+            saved: Some(0),
+        }
+    }
+
+    //Returns true if there was a change to record
+    pub fn commit(
+        &mut self,
+        content: &[TodoItem],
+        archived: &[TodoItem],
+        shifts: &[LineShift],
+        before: ViewState,
+        after: ViewState,
+    ) -> bool {
+        //Archiving always removes the lines, the content alone tells if there was a change
+        if self.nodes[self.current].snapshot.content == content {
+            return false;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: Some(self.current),
+            redo_child: None,
+            snapshot: Snapshot {
+                content: content.to_vec(),
+                archived: archived.to_vec(),
+            },
+            shifts: shifts.to_vec(),
+            before,
+            after,
+        });
+        self.nodes[self.current].redo_child = Some(idx);
+        self.current = idx;
+        true
+    }
+
+    //Along with the shifts that move the lines back, to be applied in order
+    pub fn undo(&mut self) -> Option<(Snapshot, ViewState, Vec<LineShift>)> {
+        let node = &self.nodes[self.current];
+        let parent = node.parent?;
+        let view = node.before;
+        let shifts = node.shifts.iter().rev().map(LineShift::inverse).collect();
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+        Some((self.nodes[parent].snapshot.clone(), view, shifts))
+    }
+
+    pub fn redo(&mut self) -> Option<(Snapshot, ViewState, Vec<LineShift>)> {
+        let child = self.nodes[self.current].redo_child?;
+        self.current = child;
+        let node = &self.nodes[child];
+        Some((node.snapshot.clone(), node.after, node.shifts.clone()))
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+
+    //Once written the archived items are in done.txt for good, whatever is undone after
+    pub fn clear_archived(&mut self) {
+        for node in &mut self.nodes {
+            node.snapshot.archived.clear();
+        }
+    }
+
+    //Whether the content is the same as the last time it was written
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.current)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LineShift, Snapshot, UndoTree, ViewState};
+    use crate::todo_item::TodoItem;
+
+    fn content(lines: &[&str]) -> Vec<TodoItem> {
+        lines.iter().map(|l| TodoItem::from(*l)).collect()
+    }
+
+    fn snapshot(lines: &[&str]) -> Snapshot {
+        Snapshot {
+            content: content(lines),
+            archived: Vec::new(),
+        }
+    }
+
+    fn state(step: Option<(Snapshot, ViewState, Vec<LineShift>)>) -> Option<(Snapshot, ViewState)> {
+        step.map(|(snapshot, view, _)| (snapshot, view))
+    }
+
+    fn view(y: usize) -> ViewState {
+        ViewState {
+            cursor_y: y,
+            ..ViewState::default()
+        }
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut tree = UndoTree::new(&content(&["a"]));
+        assert!(!tree.commit(&content(&["a"]), &[], &[], view(0), view(0)));
+        assert!(tree.commit(&content(&["a", "b"]), &[], &[], view(0), view(1)));
+        assert!(tree.commit(&content(&["a", "b", "c"]), &[], &[], view(1), view(2)));
+        assert!(!tree.is_saved());
+
+        assert_eq!(state(tree.undo()), Some((snapshot(&["a", "b"]), view(1))));
+        assert_eq!(state(tree.undo()), Some((snapshot(&["a"]), view(0))));
+        assert!(tree.is_saved());
+        assert_eq!(state(tree.undo()), None);
+
+        assert_eq!(state(tree.redo()), Some((snapshot(&["a", "b"]), view(1))));
+        assert_eq!(
+            state(tree.redo()),
+            Some((snapshot(&["a", "b", "c"]), view(2)))
+        );
+        assert_eq!(state(tree.redo()), None);
+    }
+
+    #[test]
+    fn archived() {
+        let mut tree = UndoTree::new(&content(&["x a", "b"]));
+        tree.commit(&content(&["b"]), &content(&["x a"]), &[], view(0), view(0));
+        assert_eq!(tree.undo().unwrap().0.archived, []);
+        assert_eq!(tree.redo().unwrap().0.archived, content(&["x a"]));
+        //Written, undoing doesn't bring it back to be written again
+        tree.clear_archived();
+        assert_eq!(tree.undo().unwrap().0.archived, []);
+        assert_eq!(tree.redo().unwrap().0.archived, []);
+    }
+
+    #[test]
+    fn branches() {
+        let mut tree = UndoTree::new(&content(&["a"]));
+        tree.commit(&content(&["b"]), &[], &[], view(0), view(0));
+        tree.undo();
+        tree.commit(&content(&["c"]), &[], &[], view(0), view(0));
+
+        //The new branch is the one redo follows
+        assert_eq!(state(tree.undo()), Some((snapshot(&["a"]), view(0))));
+        assert_eq!(state(tree.redo()), Some((snapshot(&["c"]), view(0))));
+        //But the old one is still in the tree
+        assert_eq!(tree.nodes.len(), 3);
+        assert_eq!(tree.nodes[1].snapshot.content, content(&["b"]));
+    }
+
+    #[test]
+    fn shifts() {
+        let mut tree = UndoTree::new(&content(&["a", "b", "c"]));
+        let shifts = [
+            LineShift::Remove {
+                lines: vec![0],
+                marks: vec![('m', (0, 1))],
+            },
+            LineShift::Reorder(vec![1, 0]),
+        ];
+        tree.commit(&content(&["c", "b"]), &[], &shifts, view(0), view(0));
+        //Backwards, each one undone
+        assert_eq!(
+            tree.undo().unwrap().2,
+            [
+                LineShift::Reorder(vec![1, 0]),
+                LineShift::Insert {
+                    lines: vec![0],
+                    marks: vec![('m', (0, 1))]
+                },
+            ]
+        );
+        assert_eq!(tree.redo().unwrap().2, shifts);
+        assert_eq!(
+            LineShift::Reorder(vec![2, 0, 1]).inverse(),
+            LineShift::Reorder(vec![1, 2, 0])
+        );
+    }
+}